@@ -0,0 +1,26 @@
+-- Running totals per user and per top-level folder, kept up to date on every
+-- write so quota checks never have to scan the resources table.
+CREATE TABLE IF NOT EXISTS usage (
+    scope TEXT NOT NULL,
+    key TEXT NOT NULL,
+    bytes INTEGER NOT NULL DEFAULT 0,
+    resources INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (scope, key)
+);
+
+INSERT INTO usage (scope, key, bytes, resources)
+SELECT 'user', CAST(user_id AS TEXT), SUM(COALESCE(size, 0)), COUNT(*)
+FROM resources
+GROUP BY user_id;
+
+-- The top-level folder of /a/b/c is /a; resources directly under the root count towards /
+INSERT INTO usage (scope, key, bytes, resources)
+SELECT 'folder', folder, SUM(COALESCE(size, 0)), COUNT(*)
+FROM (
+    SELECT size,
+           CASE WHEN instr(substr(path, 2), '/') = 0 THEN '/'
+                ELSE substr(path, 1, instr(substr(path, 2), '/'))
+           END AS folder
+    FROM resources
+)
+GROUP BY folder;
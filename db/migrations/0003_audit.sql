@@ -0,0 +1,18 @@
+-- Append-only record of every write. Each entry stores the hash of the one
+-- before it, so editing or deleting a row breaks the chain from that point on.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    user_id INTEGER NOT NULL,
+    client_ip TEXT,
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    old_size INTEGER,
+    new_size INTEGER,
+    content_hash TEXT,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_timestamp ON audit_log(timestamp);
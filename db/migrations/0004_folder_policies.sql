@@ -0,0 +1,10 @@
+-- Per-folder validation rules, applied on top of the global limits to every
+-- write below `folder`. The longest matching folder wins.
+CREATE TABLE IF NOT EXISTS folder_policies (
+    folder TEXT PRIMARY KEY,
+    max_folder_depth INTEGER,
+    max_resource_size INTEGER,
+    name_pattern TEXT,
+    content_types TEXT NOT NULL DEFAULT '[]',
+    read_only INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,2 @@
+-- JSON Schema that every resource below the policy's folder must conform to.
+ALTER TABLE folder_policies ADD COLUMN json_schema TEXT;
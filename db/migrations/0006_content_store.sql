@@ -0,0 +1,19 @@
+-- Each distinct body is stored once, keyed by its SHA-256, and shared by
+-- every resource with that content. `refcount` is the number of resources
+-- pointing at it; rows are deleted when it drops to zero.
+CREATE TABLE IF NOT EXISTS contents (
+    hash TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    refcount INTEGER NOT NULL
+);
+
+-- sha256() is registered on the connection by the migration runner
+INSERT INTO contents (hash, content, refcount)
+SELECT sha256(content), MIN(content), COUNT(*)
+FROM resources
+WHERE content IS NOT NULL
+GROUP BY sha256(content);
+
+ALTER TABLE resources ADD COLUMN content_hash TEXT;
+UPDATE resources SET content_hash = sha256(content) WHERE content IS NOT NULL;
+ALTER TABLE resources DROP COLUMN content;
@@ -0,0 +1,15 @@
+-- Bodies can be stored compressed; `encoding` is `identity` or `gzip` and says
+-- how `data` decodes to the text whose SHA-256 is `hash`. Existing bodies are
+-- kept as they are.
+CREATE TABLE contents_v2 (
+    hash TEXT PRIMARY KEY,
+    encoding TEXT NOT NULL DEFAULT 'identity',
+    data BLOB NOT NULL,
+    refcount INTEGER NOT NULL
+);
+
+INSERT INTO contents_v2 (hash, encoding, data, refcount)
+SELECT hash, 'identity', CAST(content AS BLOB), refcount FROM contents;
+
+DROP TABLE contents;
+ALTER TABLE contents_v2 RENAME TO contents;
@@ -0,0 +1,287 @@
+use crate::audit::AuditEvent;
+use crate::dump::{read_dump, write_dump};
+use crate::error::ApiError;
+use crate::handlers::AppState;
+use crate::models::ConflictPolicy;
+use crate::quota::Quotas;
+use crate::store::StoreError;
+use crate::validation::{normalize_request_path, validate_snapshot_name, FolderPolicy};
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize, Debug)]
+pub struct LoadQuery {
+    /// `skip`, `overwrite` or `fail` (the default).
+    pub conflict: Option<String>,
+}
+
+pub async fn handle_list_snapshots(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    if !data.store.supports_snapshots() {
+        return Err(ApiError::NotSupported("Storage backend does not support snapshots".to_string()).into());
+    }
+
+    match data.store.list_snapshots() {
+        Ok(snapshots) => Ok(HttpResponse::Ok().json(snapshots)),
+        Err(e) => {
+            error!("Failed to list snapshots: {}", e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_create_snapshot(
+    req: HttpRequest,
+    name: web::Path<String>,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let name = name.into_inner();
+    let event = AuditEvent::from_request(&req, "SNAPSHOT", &name);
+    info!("Snapshot requested: {}", name);
+
+    if !data.store.supports_snapshots() {
+        return Err(ApiError::NotSupported("Storage backend does not support snapshots".to_string()).into());
+    }
+
+    if let Err(e) = validate_snapshot_name(&name) {
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
+    }
+
+    match data.store.create_snapshot(&name) {
+        Ok(snapshot) => {
+            info!("Snapshot created: {} ({} bytes)", name, snapshot.size);
+            data.record_write(&event.succeeded(None, None));
+            Ok(HttpResponse::Created().json(snapshot))
+        }
+        Err(e) => {
+            error!("Failed to create snapshot {}: {}", name, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_restore_snapshot(
+    req: HttpRequest,
+    name: web::Path<String>,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let name = name.into_inner();
+    let event = AuditEvent::from_request(&req, "RESTORE", &name);
+    info!("Restore requested from snapshot: {}", name);
+
+    if !data.store.supports_snapshots() {
+        return Err(ApiError::NotSupported("Storage backend does not support snapshots".to_string()).into());
+    }
+
+    if let Err(e) = validate_snapshot_name(&name) {
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
+    }
+
+    // The restore itself runs on a blocking thread, it can take a while on large stores
+    let store = data.store.clone();
+    let restore_name = name.clone();
+    let result = web::block(move || store.restore_snapshot(&restore_name)).await?;
+
+    match result {
+        Ok(()) => {
+            info!("Store restored from snapshot: {}", name);
+            data.record_write(&event.succeeded(None, None));
+            Ok(HttpResponse::Ok().finish())
+        }
+        Err(e) => {
+            error!("Failed to restore snapshot {}: {}", name, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+/// Prefix of the policy routes; the rest of the request path is the folder.
+const POLICIES_PREFIX: &str = "/_admin/policies";
+
+pub async fn handle_list_policies(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    if !data.store.supports_folder_policies() {
+        return Err(ApiError::NotSupported("Storage backend does not support folder policies".to_string()).into());
+    }
+
+    match data.store.folder_policies() {
+        Ok(policies) => Ok(HttpResponse::Ok().json(policies)),
+        Err(e) => {
+            error!("Failed to list folder policies: {}", e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_set_policy(
+    req: HttpRequest,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let folder = policy_folder(&req, &data);
+    let event = AuditEvent::from_request(&req, "SET_POLICY", &folder);
+    info!("Policy update requested for folder: {}", folder);
+
+    if !data.store.supports_folder_policies() {
+        return Err(ApiError::NotSupported("Storage backend does not support folder policies".to_string()).into());
+    }
+
+    let policy = match serde_json::from_slice::<FolderPolicy>(&body) {
+        Ok(policy) => FolderPolicy { folder, ..policy },
+        Err(e) => {
+            warn!("Rejected policy for {}: {}", folder, e);
+            data.record_write(&event.failed());
+            return Err(ApiError::bad_request("invalid_policy", format!("Invalid policy: {}", e)).into());
+        }
+    };
+
+    if let Err(e) = policy.validate(&data.limits) {
+        warn!("Rejected policy for {}: {}", policy.folder, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
+    }
+
+    match data.store.set_folder_policy(&policy) {
+        Ok(()) => {
+            info!("Policy set for folder: {}", policy.folder);
+            data.record_write(&event.succeeded(None, None));
+            Ok(HttpResponse::Ok().json(policy))
+        }
+        Err(e) => {
+            error!("Failed to set policy for {}: {}", policy.folder, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_remove_policy(req: HttpRequest, data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    let folder = policy_folder(&req, &data);
+    let event = AuditEvent::from_request(&req, "REMOVE_POLICY", &folder);
+    info!("Policy removal requested for folder: {}", folder);
+
+    if !data.store.supports_folder_policies() {
+        return Err(ApiError::NotSupported("Storage backend does not support folder policies".to_string()).into());
+    }
+
+    match data.store.remove_folder_policy(&folder) {
+        Ok(policy) => {
+            info!("Policy removed from folder: {}", folder);
+            data.record_write(&event.succeeded(None, None));
+            Ok(HttpResponse::Ok().json(policy))
+        }
+        Err(e) => {
+            warn!("Failed to remove policy for {}: {}", folder, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+/// `/_admin/policies/docs/api` -> `/docs/api`, normalised like a resource path.
+fn policy_folder(req: &HttpRequest, data: &AppState) -> String {
+    let folder = req.path().strip_prefix(POLICIES_PREFIX).unwrap_or_default();
+    normalize_request_path(folder, &data.limits)
+}
+
+pub async fn handle_dump(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    info!("Dump of the whole store requested");
+
+    let store = data.store.clone();
+    let result = web::block(move || {
+        let resources = store.folder_resources("/")?;
+        let mut dump = Vec::new();
+        write_dump(&resources, &mut dump)?;
+        Ok::<_, anyhow::Error>((resources.len(), dump))
+    })
+    .await?;
+
+    match result {
+        Ok((count, dump)) => {
+            info!("Dumped {} resources", count);
+            Ok(HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .body(dump))
+        }
+        Err(e) => {
+            error!("Failed to dump store: {}", e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_load(
+    req: HttpRequest,
+    query: web::Query<LoadQuery>,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let event = AuditEvent::from_request(&req, "LOAD", "/");
+    let policy = match query.conflict.as_deref() {
+        None => ConflictPolicy::default(),
+        Some(name) => match ConflictPolicy::from_name(name) {
+            Some(policy) => policy,
+            None => {
+                return Err(ApiError::bad_request("invalid_query", format!("Unknown conflict policy: {}", name)).into());
+            }
+        },
+    };
+    info!("Load requested ({} bytes, conflict policy {:?})", body.len(), policy);
+
+    let resources = match read_dump(&body[..], &data.limits) {
+        Ok(resources) => resources,
+        Err(e) => {
+            warn!("Rejected dump: {}", e);
+            data.record_write(&event.failed());
+            return Err(ApiError::bad_request("invalid_dump", format!("Invalid dump: {}", e)).into());
+        }
+    };
+
+    // Loads restore operator-made dumps, so like snapshot restores they
+    // bypass folder policies and quotas. Conflicts are refused by the store
+    // inside its transaction
+    let store = data.store.clone();
+    let result = web::block(move || store.load_resources(&resources, policy, &Quotas::UNLIMITED)).await?;
+
+    match result {
+        Ok(report) => {
+            info!(
+                "Load finished: {} created, {} overwritten, {} skipped",
+                report.created, report.overwritten, report.skipped
+            );
+            data.record_write(&event.succeeded(None, None));
+            Ok(HttpResponse::Ok().json(report))
+        }
+        Err(e @ StoreError::Conflict(_)) => {
+            info!("Load conflicts: {}", e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+        Err(e) => {
+            error!("Failed to load dump: {}", e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+pub async fn handle_usage(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    let store = data.store.clone();
+    let result = web::block(move || store.usage()).await?;
+
+    match result {
+        Ok(usage) => Ok(HttpResponse::Ok().json(json!({
+            "quotas": data.quotas,
+            "users": usage.users,
+            "folders": usage.folders,
+        }))),
+        Err(e) => {
+            error!("Failed to read usage: {}", e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use crate::models::Resource;
+use crate::validation::{
+    normalize_path_with_limits, validate_content_with_limits, validate_path_with_limits, ValidationLimits,
+    MAX_UPLOAD_SIZE,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::collections::HashSet;
+use std::io::{self, BufWriter, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Most entries, directories included, a single archive import may hold.
+pub const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+/// Archive formats supported for folder export and import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn from_query(format: &str) -> Option<Self> {
+        match format.to_ascii_lowercase().as_str() {
+            "tar" => Some(Self::Tar),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Tar => "application/x-tar",
+            Self::Zip => "application/zip",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Tar => "tar",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+/// Packs `resources` into an in-memory archive. See [`write_archive`].
+pub fn export_archive(
+    format: ArchiveFormat,
+    folder_path: &str,
+    resources: &[Resource],
+) -> Result<Vec<u8>> {
+    let mut archive = Vec::new();
+    write_archive(format, folder_path, resources, &mut archive)?;
+    Ok(archive)
+}
+
+/// Packs `resources` into an archive written to `writer` as it is built,
+/// naming each entry by its path relative to `folder_path` and stamping it
+/// with the resource's `updated_at`.
+pub fn write_archive(
+    format: ArchiveFormat,
+    folder_path: &str,
+    resources: &[Resource],
+    writer: impl Write,
+) -> Result<()> {
+    match format {
+        ArchiveFormat::Tar => write_tar(folder_path, resources, BufWriter::new(writer)),
+        ArchiveFormat::Zip => write_zip(folder_path, resources, Spool::new(writer)),
+    }
+}
+
+/// Unpacks an archive into resources rooted at `folder_path`.
+///
+/// Every entry is checked with the same path and content rules as a regular
+/// POST; the first invalid entry rejects the whole archive. Archives with more
+/// than [`MAX_ARCHIVE_ENTRIES`] entries or unpacking to more than
+/// [`MAX_UPLOAD_SIZE`] bytes are refused as soon as the limit is crossed.
+pub fn import_archive(
+    format: ArchiveFormat,
+    folder_path: &str,
+    data: &[u8],
+    limits: &ValidationLimits,
+) -> Result<Vec<Resource>> {
+    let mut budget = Budget {
+        max_entry_size: limits.max_resource_size,
+        entries_left: MAX_ARCHIVE_ENTRIES,
+        bytes_left: MAX_UPLOAD_SIZE,
+    };
+    let entries = match format {
+        ArchiveFormat::Tar => read_tar(data, &mut budget)?,
+        ArchiveFormat::Zip => read_zip(data, &mut budget)?,
+    };
+
+    let mut resources = Vec::with_capacity(entries.len());
+    let mut seen = HashSet::new();
+    for (name, bytes, modified) in entries {
+        let path = normalize_path_with_limits(&join_path(folder_path, &name)?, limits);
+        validate_path_with_limits(&path, limits).map_err(|e| anyhow!("Invalid entry {}: {}", name, e))?;
+        if !seen.insert(path.clone()) {
+            return Err(anyhow!("Duplicate entry in archive: {}", name));
+        }
+
+        let content = String::from_utf8(bytes)
+            .map_err(|_| anyhow!("Invalid entry {}: content is not valid UTF-8 text", name))?;
+        validate_content_with_limits(&content, limits).map_err(|e| anyhow!("Invalid entry {}: {}", name, e))?;
+
+        let mut resource = Resource::new(path, content);
+        if let Some(modified) = modified {
+            resource.created_at = modified;
+            resource.updated_at = modified;
+        }
+        resources.push(resource);
+    }
+
+    Ok(resources)
+}
+
+fn relative_name(folder_path: &str, path: &str) -> String {
+    let prefix = folder_path.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .unwrap_or(path)
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// Builds the resource path for an archive entry, refusing names that try to
+/// climb out of the target folder.
+fn join_path(folder_path: &str, name: &str) -> Result<String> {
+    let mut path = folder_path.trim_end_matches('/').to_string();
+
+    for segment in name.split(['/', '\\']).filter(|s| !s.is_empty()) {
+        if segment == "." || segment == ".." {
+            return Err(anyhow!("Invalid entry name: {}", name));
+        }
+        path.push('/');
+        path.push_str(segment);
+    }
+
+    if path.is_empty() || path == folder_path.trim_end_matches('/') {
+        return Err(anyhow!("Invalid entry name: {}", name));
+    }
+
+    Ok(path)
+}
+
+fn write_tar(folder_path: &str, resources: &[Resource], writer: impl Write) -> Result<()> {
+    let mut builder = tar::Builder::new(writer);
+
+    for resource in resources {
+        let content = resource.content.as_deref().unwrap_or_default().as_bytes();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(resource.updated_at.timestamp().max(0) as u64);
+        header.set_entry_type(tar::EntryType::Regular);
+
+        builder.append_data(&mut header, relative_name(folder_path, &resource.path), content)?;
+    }
+
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+fn write_zip<W: Write>(folder_path: &str, resources: &[Resource], spool: Spool<W>) -> Result<()> {
+    let mut writer = zip::ZipWriter::new(spool);
+    // Each entry is handed on once it is complete, so only one is held at a time
+    writer.set_flush_on_finish_file(true);
+
+    for resource in resources {
+        let mut options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        if let Ok(modified) = zip::DateTime::try_from(resource.updated_at.naive_utc()) {
+            options = options.last_modified_time(modified);
+        }
+
+        writer.start_file(relative_name(folder_path, &resource.path), options)?;
+        writer.write_all(resource.content.as_deref().unwrap_or_default().as_bytes())?;
+    }
+
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+/// Holds back the bytes written since the last flush, so the zip writer can
+/// seek back and patch the header of the entry it is writing while earlier
+/// entries have already gone out to `inner`.
+struct Spool<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    /// Bytes already handed to `inner`; nothing before this can be rewritten.
+    flushed: u64,
+    position: u64,
+}
+
+impl<W: Write> Spool<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: Vec::new(),
+            flushed: 0,
+            position: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for Spool<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let start = (self.position - self.flushed) as usize;
+        let end = start + buf.len();
+        if end > self.buffer.len() {
+            self.buffer.resize(end, 0);
+        }
+        self.buffer[start..end].copy_from_slice(buf);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buffer)?;
+        self.flushed += self.buffer.len() as u64;
+        self.position = self.flushed;
+        self.buffer.clear();
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Seek for Spool<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self.flushed + self.buffer.len() as u64;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => end.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) if (self.flushed..=end).contains(&position) => {
+                self.position = position;
+                Ok(position)
+            }
+            _ => Err(io::Error::new(ErrorKind::Unsupported, "cannot seek outside the unflushed entry")),
+        }
+    }
+}
+
+/// The zip writer only reads back when copying entries, which export never does.
+impl<W: Write> Read for Spool<W> {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(ErrorKind::Unsupported, "archive export is write-only"))
+    }
+}
+
+type ArchiveEntry = (String, Vec<u8>, Option<DateTime<Utc>>);
+
+/// What is left of the entry count and unpacked size an import may use.
+struct Budget {
+    max_entry_size: usize,
+    entries_left: usize,
+    bytes_left: usize,
+}
+
+impl Budget {
+    fn take_entries(&mut self, count: usize) -> Result<()> {
+        self.entries_left = self
+            .entries_left
+            .checked_sub(count)
+            .ok_or_else(|| anyhow!("Archive has more than {} entries", MAX_ARCHIVE_ENTRIES))?;
+        Ok(())
+    }
+
+    /// Reads at most one byte past the resource size limit, so oversized or
+    /// maliciously compressed entries are rejected without being fully
+    /// inflated, and stops as soon as the archive as a whole grows too large.
+    fn read(&mut self, reader: impl Read) -> Result<Vec<u8>> {
+        let limit = self.max_entry_size.min(self.bytes_left) + 1;
+        let mut bytes = Vec::new();
+        reader.take(limit as u64).read_to_end(&mut bytes)?;
+
+        self.bytes_left = self
+            .bytes_left
+            .checked_sub(bytes.len())
+            .ok_or_else(|| anyhow!("Archive unpacks to more than {} bytes", MAX_UPLOAD_SIZE))?;
+        Ok(bytes)
+    }
+}
+
+fn read_tar(data: &[u8], budget: &mut Budget) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(data);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        budget.take_entries(1)?;
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous => {}
+            tar::EntryType::Directory => continue,
+            other => return Err(anyhow!("Unsupported archive entry type: {:?}", other)),
+        }
+
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let modified = entry
+            .header()
+            .mtime()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, 0));
+
+        let bytes = budget.read(&mut entry)?;
+        entries.push((name, bytes, modified));
+    }
+
+    Ok(entries)
+}
+
+fn read_zip(data: &[u8], budget: &mut Budget) -> Result<Vec<ArchiveEntry>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    budget.take_entries(archive.len())?;
+    let mut entries = Vec::with_capacity(archive.len());
+
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let name = file.name().to_string();
+        let modified = file
+            .last_modified()
+            .and_then(|dt| NaiveDateTime::try_from(dt).ok())
+            .map(|dt| dt.and_utc());
+
+        let bytes = budget.read(&mut file)?;
+        entries.push((name, bytes, modified));
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,234 @@
+use crate::error::ApiError;
+use crate::handlers::AppState;
+use crate::models::{Resource, DEFAULT_USER_ID};
+use crate::validation::normalize_path_with_limits;
+use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+/// `prev_hash` of the first entry in the chain.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A write attempt as seen by a handler, before it is added to the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEvent {
+    pub user_id: i64,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub success: bool,
+    /// Size of the resource before the write, if it existed.
+    pub old_size: Option<i64>,
+    /// Size of the resource after the write, if it still exists.
+    pub new_size: Option<i64>,
+    /// SHA-256 of the content written.
+    pub content_hash: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(method: &str, path: &str) -> Self {
+        Self {
+            user_id: DEFAULT_USER_ID,
+            client_ip: None,
+            method: method.to_string(),
+            path: path.to_string(),
+            success: false,
+            old_size: None,
+            new_size: None,
+            content_hash: None,
+        }
+    }
+
+    /// An event for `req`, attributed to the address of the connected peer.
+    pub fn from_request(req: &HttpRequest, method: &str, path: &str) -> Self {
+        Self {
+            client_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+            ..Self::new(method, path)
+        }
+    }
+
+    /// The same attempt on another path, e.g. one resource of an archive.
+    pub fn at_path(&self, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn failed(&self) -> Self {
+        Self {
+            success: false,
+            ..self.clone()
+        }
+    }
+
+    /// The attempt succeeded, replacing a resource of `old_size` (if any)
+    /// with `content` (if any).
+    pub fn succeeded(&self, old_size: Option<i64>, content: Option<&str>) -> Self {
+        Self {
+            success: true,
+            old_size,
+            new_size: content.map(|c| c.len() as i64),
+            content_hash: content.map(content_hash),
+            ..self.clone()
+        }
+    }
+
+    /// The attempt succeeded, creating `resource`. Size and hash come from
+    /// its metadata, so the content need not have been loaded.
+    pub fn created(&self, resource: &Resource) -> Self {
+        Self {
+            success: true,
+            old_size: None,
+            new_size: Some(resource.size),
+            content_hash: resource.content_hash.clone(),
+            ..self.clone()
+        }
+    }
+
+    pub fn outcome(&self) -> &'static str {
+        if self.success {
+            "success"
+        } else {
+            "failure"
+        }
+    }
+}
+
+/// One row of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub user_id: i64,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub outcome: String,
+    pub old_size: Option<i64>,
+    pub new_size: Option<i64>,
+    pub content_hash: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Chains `event` after the entry whose hash is `prev_hash`.
+    pub fn chain(id: i64, timestamp: DateTime<Utc>, prev_hash: &str, event: &AuditEvent) -> Self {
+        let mut entry = Self {
+            id,
+            timestamp,
+            user_id: event.user_id,
+            client_ip: event.client_ip.clone(),
+            method: event.method.clone(),
+            path: event.path.clone(),
+            outcome: event.outcome().to_string(),
+            old_size: event.old_size,
+            new_size: event.new_size,
+            content_hash: event.content_hash.clone(),
+            prev_hash: prev_hash.to_string(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        entry
+    }
+
+    /// Hash over the previous entry's hash and every field of this one.
+    pub fn compute_hash(&self) -> String {
+        let fields = json!([
+            self.id,
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.user_id,
+            self.client_ip,
+            self.method,
+            self.path,
+            self.outcome,
+            self.old_size,
+            self.new_size,
+            self.content_hash,
+        ]);
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(fields.to_string().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Restricts which entries `GET /_audit` returns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    /// Only entries for this path or paths below it.
+    pub under: Option<String>,
+    /// Only entries recorded at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    pub user_id: Option<i64>,
+}
+
+/// Checks that every entry, in id order, links to the one before it and
+/// still matches its hash. Returns a description of every break found.
+///
+/// Removing entries from the end of the chain cannot be detected this way.
+pub fn verify_chain(entries: &[AuditEntry]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut prev_hash = GENESIS_HASH;
+
+    for entry in entries {
+        if entry.prev_hash != prev_hash {
+            problems.push(format!("audit entry {}: does not link to the entry before it", entry.id));
+        }
+        if entry.compute_hash() != entry.hash {
+            problems.push(format!("audit entry {}: contents do not match its hash", entry.id));
+        }
+        prev_hash = &entry.hash;
+    }
+
+    problems
+}
+
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AuditQuery {
+    pub under: Option<String>,
+    /// RFC 3339 timestamp.
+    pub since: Option<String>,
+    pub user: Option<i64>,
+}
+
+pub async fn handle_audit(
+    query: web::Query<AuditQuery>,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    if !data.store.supports_audit() {
+        return Err(ApiError::NotSupported("Storage backend does not keep an audit log".to_string()).into());
+    }
+
+    let since = match query.since.as_deref().map(DateTime::parse_from_rfc3339) {
+        None => None,
+        Some(Ok(since)) => Some(since.with_timezone(&Utc)),
+        Some(Err(e)) => {
+            return Err(ApiError::bad_request("invalid_query", format!("Invalid since timestamp: {}", e)).into());
+        }
+    };
+    let filter = AuditFilter {
+        under: query.under.as_deref().map(|under| normalize_path_with_limits(under, &data.limits)),
+        since,
+        user_id: query.user,
+    };
+
+    let store = data.store.clone();
+    match web::block(move || store.audit_entries(&filter)).await? {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
+        Err(e) => {
+            error!("Failed to read the audit log: {}", e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::database::Database;
+use crate::dump::{read_dump, write_dump};
+use crate::models::{ConflictPolicy, Resource};
+use crate::quota::Quotas;
+use crate::settings::{Settings, StorageBackend};
+use crate::store::{open_store, ResourceStore, StoreError, StoreResult};
+use crate::validation::{
+    normalize_path_with_limits, validate_content_with_limits, validate_path_with_limits, ValidationLimits,
+};
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[command(name = "gresources", version, about = "Hierarchical text resource server")]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalOptions,
+
+    /// Defaults to `serve` when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct GlobalOptions {
+    /// Settings file to use instead of ./Settings.toml
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Database file, overriding `db_file_path`
+    #[arg(long, global = true, value_name = "FILE")]
+    pub db: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the HTTP server
+    Serve {
+        /// Address to bind, overriding `host`
+        #[arg(long)]
+        host: Option<String>,
+        /// Port to bind, overriding `port`
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Create the database and apply all migrations
+    InitDb,
+    /// Apply pending schema migrations
+    Migrate {
+        /// Only list the migrations that would run
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write every resource as NDJSON to FILE or stdout
+    Dump { file: Option<PathBuf> },
+    /// Load an NDJSON dump
+    Load {
+        file: PathBuf,
+        /// What to do with paths that already exist
+        #[arg(long, value_enum, default_value_t = ConflictArg::Fail)]
+        conflict: ConflictArg,
+    },
+    /// List the resources below a folder
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+    },
+    /// Print a resource's content
+    Cat { path: String },
+    /// Create or replace a resource with the content of FILE or stdin
+    Put { path: String, file: Option<PathBuf> },
+    /// Delete a resource or an empty folder
+    Rm { path: String },
+    /// Check the database for corruption and inconsistencies
+    Check,
+    /// Verify that the audit log's hash chain is intact
+    VerifyAudit,
+    /// Take a named snapshot of the database
+    Snapshot { name: String },
+    /// List snapshots
+    Snapshots,
+    /// Restore the database from a snapshot
+    Restore { name: String },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ConflictArg {
+    Skip,
+    Overwrite,
+    Fail,
+}
+
+impl From<ConflictArg> for ConflictPolicy {
+    fn from(arg: ConflictArg) -> Self {
+        match arg {
+            ConflictArg::Skip => ConflictPolicy::Skip,
+            ConflictArg::Overwrite => ConflictPolicy::Overwrite,
+            ConflictArg::Fail => ConflictPolicy::Fail,
+        }
+    }
+}
+
+impl GlobalOptions {
+    /// Loads the settings file and applies the command-line overrides.
+    pub fn load_settings(&self) -> Result<Settings> {
+        let mut settings = match &self.config {
+            Some(path) => Settings::load_from(path)?,
+            None => Settings::load()?,
+        };
+
+        if let Some(db) = &self.db {
+            settings.db_file_path = db.clone();
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Runs an administrative command directly against the configured store.
+/// `serve` is handled by the binary itself.
+pub fn run_command(command: Command, settings: &Settings) -> Result<()> {
+    match command {
+        Command::Serve { .. } => return Err(anyhow!("serve must be run by the server binary")),
+        Command::InitDb => {
+            Database::new(settings)?;
+            println!("Database ready at {}", settings.db_file_path);
+        }
+        Command::Migrate { dry_run } => {
+            let migrations = Database::migrate(settings, dry_run)?;
+            if migrations.is_empty() {
+                println!("Database schema is up to date");
+            }
+            for migration in &migrations {
+                let state = if dry_run { "Pending" } else { "Applied" };
+                println!("{} migration {}: {}", state, migration.version, migration.name);
+            }
+        }
+        Command::Dump { file } => {
+            let resources = open_existing_store(settings, true)?.folder_resources("/")?;
+            match file {
+                Some(file) => write_dump(&resources, BufWriter::new(File::create(file)?))?,
+                None => write_dump(&resources, std::io::stdout().lock())?,
+            }
+            eprintln!("Dumped {} resources", resources.len());
+        }
+        Command::Load { file, conflict } => {
+            let reader = BufReader::new(File::open(&file)?);
+            let resources = read_dump(reader, &settings.validation_limits())?;
+            // The command line is an operator tool and bypasses policies and quotas
+            let report = open_store(settings)?.load_resources(&resources, conflict.into(), &Quotas::UNLIMITED)?;
+            println!(
+                "Loaded {}: {} created, {} overwritten, {} skipped",
+                file.display(),
+                report.created,
+                report.overwritten,
+                report.skipped
+            );
+        }
+        Command::Ls { path } => {
+            let path = checked_path(&path, &settings.validation_limits())?;
+            for resource in open_existing_store(settings, true)?.list_folder_resources(&path)?.resources {
+                println!("{}", resource);
+            }
+        }
+        Command::Cat { path } => {
+            let path = checked_path(&path, &settings.validation_limits())?;
+            let resource = open_existing_store(settings, true)?
+                .get_resource(&path)?
+                .ok_or_else(|| anyhow!("Resource not found: {}", path))?;
+            print!("{}", resource.content.unwrap_or_default());
+        }
+        Command::Put { path, file } => {
+            let path = checked_path(&path, &settings.validation_limits())?;
+            let mut content = String::new();
+            match file {
+                Some(file) => File::open(file)?.read_to_string(&mut content)?,
+                None => std::io::stdin().read_to_string(&mut content)?,
+            };
+            validate_content_with_limits(&content, &settings.validation_limits())?;
+
+            // Like `load`, bypasses policies and quotas
+            let store = open_store(settings)?;
+            if store.resource_exists(&path)? {
+                store.update_resource(&path, &content, &Quotas::UNLIMITED)?;
+                println!("Updated {} ({} bytes)", path, content.len());
+            } else {
+                store.create_resource(&Resource::new(path.clone(), content.clone()), &Quotas::UNLIMITED)?;
+                println!("Created {} ({} bytes)", path, content.len());
+            }
+        }
+        Command::Rm { path } => {
+            let path = checked_path(&path, &settings.validation_limits())?;
+            let store = open_existing_store(settings, false)?;
+            remove(store, &path)?;
+            println!("Removed {}", path);
+        }
+        Command::Check => {
+            let problems = Database::open_existing(settings, true)?.check_integrity()?;
+            if problems.is_empty() {
+                println!("No problems found");
+            } else {
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                return Err(anyhow!("{} problem(s) found", problems.len()));
+            }
+        }
+        Command::VerifyAudit => {
+            let problems = Database::open_existing(settings, true)?.verify_audit()?;
+            if problems.is_empty() {
+                println!("Audit log intact");
+            } else {
+                for problem in &problems {
+                    println!("{}", problem);
+                }
+                return Err(anyhow!("{} problem(s) found", problems.len()));
+            }
+        }
+        Command::Snapshot { name } => {
+            let snapshot = Database::open_existing(settings, false)?.create_snapshot(&name)?;
+            println!("Snapshot {} created ({} bytes)", snapshot.name, snapshot.size);
+        }
+        Command::Snapshots => {
+            for snapshot in Database::open_existing(settings, true)?.list_snapshots()? {
+                println!(
+                    "{}\t{}\t{}",
+                    snapshot.name,
+                    snapshot.size,
+                    snapshot.created_at.format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                );
+            }
+        }
+        Command::Restore { name } => {
+            Database::open_existing(settings, false)?.restore_snapshot(&name)?;
+            println!("Database restored from snapshot {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the configured store for a command that must not create or migrate
+/// the database. Only `init-db`, `migrate`, `put` and `load` may do that.
+fn open_existing_store(settings: &Settings, read_only: bool) -> Result<Arc<dyn ResourceStore>> {
+    match settings.storage_backend {
+        StorageBackend::Sqlite => Ok(Arc::new(Database::open_existing(settings, read_only)?)),
+        _ => open_store(settings),
+    }
+}
+
+fn checked_path(path: &str, limits: &ValidationLimits) -> Result<String> {
+    let path = normalize_path_with_limits(path, limits);
+    validate_path_with_limits(&path, limits)?;
+    Ok(path)
+}
+
+fn remove(store: Arc<dyn ResourceStore>, path: &str) -> StoreResult<()> {
+    match store.delete_resource(path) {
+        Err(StoreError::NotFound(_)) => {}
+        result => return result.map(|_| ()),
+    }
+
+    if store.folder_is_empty(path)? {
+        Err(StoreError::NotFound(format!("Resource or folder {}", path)))
+    } else {
+        Err(StoreError::NotEmpty(path.to_string()))
+    }
+}
@@ -1,162 +1,408 @@
-use crate::models::{FolderInfo, Resource};
+use crate::audit::{content_hash, verify_chain, AuditEntry, AuditEvent, AuditFilter, GENESIS_HASH};
+use crate::metrics::metrics;
+use crate::migrations::{pending_migrations, run_migrations, schema_drift, Migration};
+use crate::models::{
+    parent_folders, top_level_folder, ConflictPolicy, FolderInfo, FolderStats, LoadReport, Resource, ResourceSize,
+    SnapshotInfo, Usage, UsageReport, LARGEST_RESOURCES,
+};
+use crate::quota::{Quotas, UsageChange};
 use crate::settings::Settings;
+use crate::store::{check_quotas, ResourceStore, StoreError, StoreResult};
+use crate::validation::{governing_policy, validate_path, validate_snapshot_name, FolderPolicy};
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, SecondsFormat, Utc};
-use rusqlite::{params, Connection, Row};
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use rusqlite::backup::Progress;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension, Row, MAIN_DB};
 use std::fs;
-use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
 
 pub struct Database {
     connection: Arc<Mutex<Connection>>,
+    snapshot_dir: PathBuf,
+    /// Bodies at least this large are stored gzip-compressed.
+    compression_min_bytes: Option<usize>,
 }
 
 impl Database {
     pub fn new(settings: &Settings) -> Result<Self> {
-        // Create the db directory if it doesn't exist
-        if let Some(parent) = Path::new(&settings.db_file_path).parent() {
-            fs::create_dir_all(parent)?;
-        }
+        let mut conn = Self::open_connection(settings)?;
 
-        let conn = Connection::open(&settings.db_file_path)?;
+        // Bring the schema up to date before serving anything
+        let applied = Self::apply_migrations(&mut conn, settings, false)?;
+        if applied.is_empty() {
+            info!("Database schema is up to date");
+        }
 
-        // Initialize the database schema only if needed
-        Self::initialize_schema(&conn, &settings.db_schema_path)?;
+        for drift in schema_drift(&conn)? {
+            warn!("Database schema drift: {}", drift);
+        }
 
         Ok(Self {
             connection: Arc::new(Mutex::new(conn)),
+            snapshot_dir: settings.snapshot_directory(),
+            compression_min_bytes: Some(settings.compression_min_bytes).filter(|min| *min > 0),
         })
     }
 
-    fn initialize_schema(conn: &Connection, schema_path: &str) -> Result<()> {
-        // Check if the resources table exists
-        let table_exists: bool = conn.query_row(
-            "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='resources'",
-            [],
-            |row| {
-                let count: i64 = row.get(0)?;
-                Ok(count > 0)
-            },
-        )?;
+    /// Opens the configured database as it is, for commands that must not
+    /// create it or change its schema. With `read_only` nothing can be written.
+    pub fn open_existing(settings: &Settings, read_only: bool) -> Result<Self> {
+        let path = Path::new(&settings.db_file_path);
+        if !path.is_file() {
+            return Err(anyhow!("Database {} does not exist, run init-db first", settings.db_file_path));
+        }
 
-        if !table_exists {
-            println!("Initializing database schema...");
-            let schema = fs::read_to_string(schema_path)?;
-            conn.execute_batch(&schema)?;
-            println!("Database schema initialized successfully");
+        let access = if read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
         } else {
-            println!("Database schema already exists, skipping initialization");
-        }
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+        };
+        let conn = Connection::open_with_flags(path, access | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
 
-        Ok(())
+        Ok(Self {
+            connection: Arc::new(Mutex::new(conn)),
+            snapshot_dir: settings.snapshot_directory(),
+            compression_min_bytes: Some(settings.compression_min_bytes).filter(|min| *min > 0),
+        })
     }
 
-    pub fn create_resource(&self, resource: &Resource) -> Result<i64> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+    /// Runs the pending schema migrations against the configured database
+    /// without starting the server. With `dry_run` only reports what would run.
+    pub fn migrate(settings: &Settings, dry_run: bool) -> Result<Vec<&'static Migration>> {
+        let mut conn = Self::open_connection(settings)?;
+        Self::apply_migrations(&mut conn, settings, dry_run)
+    }
+
+    /// Describes how the live schema differs from what the code expects.
+    pub fn schema_drift(&self) -> StoreResult<Vec<String>> {
+        let conn = self.lock()?;
+
+        schema_drift(&conn).map_err(schema_error)
+    }
+
+    /// Runs SQLite's integrity check, the schema drift check and per-row
+    /// consistency checks, returning a description of every problem found.
+    pub fn check_integrity(&self) -> StoreResult<Vec<String>> {
+        let conn = self.lock()?;
+
+        let mut problems = Vec::new();
+
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        for message in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let message = message?;
+            if message != "ok" {
+                problems.push(format!("integrity: {}", message));
+            }
+        }
+
+        problems.extend(schema_drift(&conn).map_err(schema_error)?.into_iter().map(|d| format!("schema: {}", d)));
 
         let mut stmt = conn.prepare(
-            "INSERT INTO resources (user_id, path, content, size, created_at, updated_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "SELECT r.user_id, r.path, c.encoding, c.data, r.size, r.created_at, r.updated_at, r.content_hash
+             FROM resources r LEFT JOIN contents c ON c.hash = r.content_hash
+             ORDER BY r.path",
         )?;
+        let rows = stmt.query_map([], |row| {
+            let content = match (row.get::<_, Option<String>>(2)?, row.get::<_, Option<Vec<u8>>>(3)?) {
+                (Some(encoding), Some(data)) => Some(decode_content(&encoding, &data)),
+                _ => None,
+            };
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                content,
+                row.get::<_, Option<i64>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+            ))
+        })?;
 
-        let id = stmt.insert(params![
-            resource.user_id,
-            resource.path,
-            resource.content,
-            resource.size,
-            resource
-                .created_at
-                .to_rfc3339_opts(SecondsFormat::Millis, true)
-                .to_string(),
-            resource
-                .updated_at
-                .to_rfc3339_opts(SecondsFormat::Millis, true)
-                .to_string(),
-        ])?;
+        let mut expected_usage = UsageReport::default();
+        for row in rows {
+            let (user_id, path, content, size, created_at, updated_at, hash) = row?;
+            expected_usage.add(user_id, &path, size.unwrap_or(0), 1);
 
-        Ok(id)
-    }
+            if let Err(e) = validate_path(&path) {
+                problems.push(format!("{}: invalid path: {}", path, e));
+            }
 
-    pub fn get_resource(&self, path: &str) -> Result<Option<Resource>> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+            if let (Some(hash), None) = (&hash, &content) {
+                problems.push(format!("{}: content {} is missing", path, hash));
+            }
+            // Undecodable content is reported with the content checks below
+            let content = content.and_then(Result::ok);
+
+            let actual_size = content.as_deref().map(str::len).unwrap_or(0) as i64;
+            if size != Some(actual_size) {
+                problems.push(format!(
+                    "{}: recorded size {:?} does not match content length {}",
+                    path, size, actual_size
+                ));
+            }
+
+            for (column, value) in [("created_at", created_at), ("updated_at", updated_at)] {
+                let valid = value
+                    .as_deref()
+                    .is_some_and(|v| DateTime::parse_from_rfc3339(v).is_ok());
+                if !valid {
+                    problems.push(format!("{}: invalid {} {:?}", path, column, value));
+                }
+            }
+        }
 
         let mut stmt = conn.prepare(
-            "SELECT id, user_id, path, content, size, created_at, updated_at 
-             FROM resources WHERE path = ?1",
+            "SELECT c.hash, c.encoding, c.data, c.refcount, COUNT(r.id)
+             FROM contents c LEFT JOIN resources r ON r.content_hash = c.hash
+             GROUP BY c.hash ORDER BY c.hash",
         )?;
-
-        let result = stmt.query_row(params![path], |row| {
-            // Better error handling - let's see exactly what's causing the issue
-            match self.row_to_resource(row) {
-                Ok(resource) => Ok(resource),
-                Err(e) => {
-                    // Log the actual error to help debug
-                    eprintln!("Error converting row to resource: {}", e);
-                    Err(rusqlite::Error::InvalidColumnType(
-                        0,
-                        format!("conversion error: {}", e),
-                        rusqlite::types::Type::Null,
-                    ))
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                decode_content(&row.get::<_, String>(1)?, &row.get::<_, Vec<u8>>(2)?),
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?;
+        for row in rows {
+            let (hash, content, refcount, references) = row?;
+            match content {
+                Ok(content) if content_hash(&content) != hash => {
+                    problems.push(format!("content {}: does not match its hash", hash));
                 }
+                Ok(_) => {}
+                Err(e) => problems.push(format!("content {}: {}", hash, e)),
+            }
+            if refcount != references {
+                problems.push(format!(
+                    "content {}: reference count {} but {} resources refer to it",
+                    hash, refcount, references
+                ));
             }
-        });
+        }
 
-        match result {
-            Ok(resource) => Ok(Some(resource)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(anyhow!("Database query error: {}", e)),
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT parent.path FROM resources parent JOIN resources child
+             ON child.path > parent.path || '/' AND child.path < parent.path || '0'
+             ORDER BY parent.path",
+        )?;
+        for path in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            problems.push(format!("{}: is both a resource and a folder", path?));
         }
+
+        if let Ok(usage) = Self::read_usage(&conn) {
+            if usage != expected_usage {
+                problems.push("usage: recorded totals do not match the stored resources".to_string());
+            }
+        }
+
+        problems.extend(verify_chain(&Self::read_audit_entries(&conn, &AuditFilter::default())?));
+
+        Ok(problems)
     }
 
-    pub fn update_resource(&self, path: &str, content: &str) -> Result<()> {
+    /// Acquires the connection, recording how long the caller had to wait for it.
+    fn lock(&self) -> StoreResult<MutexGuard<'_, Connection>> {
+        let start = Instant::now();
         let conn = self
             .connection
             .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+            .map_err(|_| StoreError::LockPoisoned("database"))?;
+        metrics().db_lock_wait.observe(start.elapsed().as_secs_f64());
+
+        Ok(conn)
+    }
+
+    fn open_connection(settings: &Settings) -> Result<Connection> {
+        // Create the db directory if it doesn't exist
+        if let Some(parent) = Path::new(&settings.db_file_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(Connection::open(&settings.db_file_path)?)
+    }
+
+    fn apply_migrations(
+        conn: &mut Connection,
+        settings: &Settings,
+        dry_run: bool,
+    ) -> Result<Vec<&'static Migration>> {
+        // The embedded schema is used unless an override file is configured.
+        // The override only replaces the initial migration, so it is not read
+        // at all once that has been applied
+        let initial_schema = match &settings.db_schema_path {
+            Some(path) if pending_migrations(conn)?.iter().any(|m| m.version == 1) => {
+                match fs::read_to_string(path) {
+                    Ok(schema) => Some(schema),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        warn!("Schema override {} not found, using the embedded schema", path);
+                        None
+                    }
+                    Err(e) => return Err(anyhow!("Failed to read schema override {}: {}", path, e)),
+                }
+            }
+            _ => None,
+        };
+        let migrations = run_migrations(conn, initial_schema.as_deref(), dry_run)?;
+
+        if !dry_run {
+            for migration in &migrations {
+                info!("Applied migration {}: {}", migration.version, migration.name);
+            }
+        }
+
+        Ok(migrations)
+    }
+}
+
+impl ResourceStore for Database {
+    fn create_resource(&self, resource: &Resource, quotas: &Quotas) -> StoreResult<i64> {
+        let mut conn = self.lock()?;
+
+        let tx = conn.transaction()?;
+        if Self::owner_and_size(&tx, &resource.path)?.is_some() {
+            return Err(StoreError::Conflict(format!("Resource {}", resource.path)));
+        }
+        Self::check_namespace(&tx, &resource.path)?;
+        check_quotas(quotas, &[UsageChange::created(resource)], || Self::read_usage(&tx))?;
+        let id = self.insert_resource(&tx, resource)?;
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    fn get_resource(&self, path: &str) -> StoreResult<Option<Resource>> {
+        let conn = self.lock()?;
+
+        self.find_resource(&conn, path)
+    }
+
+    fn get_resource_gzipped(&self, path: &str) -> StoreResult<Option<(Resource, Option<Vec<u8>>)>> {
+        let conn = self.lock()?;
+
+        self.find_stored(&conn, path, true)
+    }
+
+    fn update_resource(&self, path: &str, content: &str, quotas: &Quotas) -> StoreResult<Resource> {
+        let mut conn = self.lock()?;
 
         let size = content.len() as i64;
         let updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).to_string();
 
-        let mut stmt = conn.prepare(
-            "UPDATE resources SET content = ?1, size = ?2, updated_at = ?3 WHERE path = ?4",
+        let tx = conn.transaction()?;
+        let previous = self
+            .find_resource(&tx, path)?
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", path)))?;
+        let change = UsageChange {
+            user_id: previous.user_id,
+            path: path.to_string(),
+            bytes: size - previous.size,
+            resources: 0,
+        };
+        check_quotas(quotas, &[change], || Self::read_usage(&tx))?;
+
+        // Take the new reference first, rewriting the same content must not drop it
+        let hash = self.store_content(&tx, Some(content))?;
+        tx.execute(
+            "UPDATE resources SET content_hash = ?1, size = ?2, updated_at = ?3 WHERE path = ?4",
+            params![hash, size, updated_at, path],
         )?;
+        Self::release_content(&tx, previous.content_hash.as_deref())?;
+        Self::charge_usage(&tx, previous.user_id, path, size - previous.size, 0)?;
+        tx.commit()?;
 
-        let rows_affected = stmt.execute(params![content, size, updated_at, path])?;
+        Ok(previous)
+    }
 
-        if rows_affected == 0 {
-            return Err(anyhow!("Resource not found"));
-        }
+    fn delete_resource(&self, path: &str) -> StoreResult<Resource> {
+        let mut conn = self.lock()?;
 
-        Ok(())
+        let tx = conn.transaction()?;
+        let deleted = self
+            .find_resource(&tx, path)?
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", path)))?;
+
+        tx.execute("DELETE FROM resources WHERE path = ?1", params![path])?;
+        Self::release_content(&tx, deleted.content_hash.as_deref())?;
+        Self::charge_usage(&tx, deleted.user_id, path, -deleted.size, -1)?;
+        tx.commit()?;
+
+        Ok(deleted)
     }
 
-    pub fn delete_resource(&self, path: &str) -> Result<()> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+    fn copy_resource(
+        &self,
+        from: &str,
+        to: &str,
+        content_type: Option<&str>,
+        quotas: &Quotas,
+    ) -> StoreResult<Resource> {
+        let mut conn = self.lock()?;
 
-        let mut stmt = conn.prepare("DELETE FROM resources WHERE path = ?1")?;
-        let rows_affected = stmt.execute(params![path])?;
+        let tx = conn.transaction()?;
+        let (user_id, size, hash): (i64, i64, Option<String>) = tx
+            .query_row(
+                "SELECT user_id, COALESCE(size, 0), content_hash FROM resources WHERE path = ?1",
+                [from],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", from)))?;
+        if Self::owner_and_size(&tx, to)?.is_some() {
+            return Err(StoreError::Conflict(format!("Resource {}", to)));
+        }
+        Self::check_namespace(&tx, to)?;
 
-        if rows_affected == 0 {
-            return Err(anyhow!("Resource not found"));
+        // The policy is checked against the row being copied; only a JSON
+        // Schema needs its content loaded
+        if let Some(policy) = governing_policy(&Self::read_policies(&tx)?, to) {
+            policy.check_metadata(to, size as usize, content_type)?;
+            if policy.json_schema.is_some() {
+                let source = self
+                    .find_resource(&tx, from)?
+                    .ok_or_else(|| StoreError::NotFound(format!("Resource {}", from)))?;
+                policy.check_content(source.content.as_deref().unwrap_or_default())?;
+            }
         }
+        let change = UsageChange {
+            user_id,
+            path: to.to_string(),
+            bytes: size,
+            resources: 1,
+        };
+        check_quotas(quotas, &[change], || Self::read_usage(&tx))?;
 
-        Ok(())
+        // The copy shares the stored content, only its reference count changes
+        if let Some(hash) = &hash {
+            tx.execute("UPDATE contents SET refcount = refcount + 1 WHERE hash = ?1", [hash])?;
+        }
+        let now = Utc::now();
+        let mut copy = Resource {
+            id: None,
+            user_id,
+            path: to.to_string(),
+            content: None,
+            content_hash: hash.clone(),
+            size,
+            created_at: now,
+            updated_at: now,
+        };
+        copy.id = Some(Self::insert_row(&tx, &copy, hash.as_deref())?);
+        tx.commit()?;
+
+        Ok(copy)
     }
 
-    pub fn list_folder_resources(&self, folder_path: &str) -> Result<FolderInfo> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+    fn list_folder_resources(&self, folder_path: &str) -> StoreResult<FolderInfo> {
+        let conn = self.lock()?;
 
         // Normalize folder path
         let normalized_folder = if folder_path == "/" {
@@ -165,19 +411,22 @@ impl Database {
             folder_path.trim_end_matches('/').to_string()
         };
 
-        // Get all resources that start with the folder path
+        // Get all resources below the folder path. A binary range rather than
+        // LIKE, which would ignore case and treat `_` and `%` as wildcards
         let mut stmt = conn.prepare(
             "SELECT path, created_at FROM resources 
-             WHERE (path LIKE ?1 OR path = ?2)
+             WHERE (path > ?1 AND path < ?2) OR path = ?3
              ORDER BY path",
         )?;
 
-        let pattern = format!("{}/%", normalized_folder);
-        let rows = stmt.query_map(params![pattern, normalized_folder], |row| {
-            let path: String = row.get(0)?;
-            let created_at_str: String = row.get(1)?;
-            Ok((path, created_at_str))
-        })?;
+        let rows = stmt.query_map(
+            params![format!("{}/", normalized_folder), format!("{}0", normalized_folder), normalized_folder],
+            |row| {
+                let path: String = row.get(0)?;
+                let created_at_str: String = row.get(1)?;
+                Ok((path, created_at_str))
+            },
+        )?;
 
         let mut resources = Vec::new();
         let mut folder_created_at = Utc::now();
@@ -211,7 +460,7 @@ impl Database {
         }
 
         if !found_folder && resources.is_empty() {
-            return Err(anyhow!("Folder not found"));
+            return Err(StoreError::NotFound(format!("Folder {}", folder_path)));
         }
 
         Ok(FolderInfo {
@@ -221,11 +470,8 @@ impl Database {
         })
     }
 
-    pub fn resource_exists(&self, path: &str) -> Result<bool> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+    fn resource_exists(&self, path: &str) -> StoreResult<bool> {
+        let conn = self.lock()?;
 
         let mut stmt = conn.prepare("SELECT COUNT(*) FROM resources WHERE path = ?1")?;
         let count: i64 = stmt.query_row(params![path], |row| row.get(0))?;
@@ -233,46 +479,759 @@ impl Database {
         Ok(count > 0)
     }
 
-    pub fn folder_is_empty(&self, folder_path: &str) -> Result<bool> {
-        let conn = self
-            .connection
-            .lock()
-            .map_err(|_| anyhow!("Failed to acquire database lock"))?;
+    fn folder_is_empty(&self, folder_path: &str) -> StoreResult<bool> {
+        let conn = self.lock()?;
 
         let normalized_folder = folder_path.trim_end_matches('/');
-        let pattern = format!("{}/%", normalized_folder);
 
-        let mut stmt = conn.prepare("SELECT COUNT(*) FROM resources WHERE path LIKE ?1")?;
-        let count: i64 = stmt.query_row(params![pattern], |row| row.get(0))?;
+        // Same binary range as `check_namespace`
+        let has_children: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM resources WHERE path > ?1 AND path < ?2)",
+            params![format!("{}/", normalized_folder), format!("{}0", normalized_folder)],
+            |row| row.get(0),
+        )?;
 
-        Ok(count == 0)
+        Ok(!has_children)
     }
+    fn load_resources(
+        &self,
+        resources: &[Resource],
+        policy: ConflictPolicy,
+        quotas: &Quotas,
+    ) -> StoreResult<LoadReport> {
+        let mut conn = self.lock()?;
+
+        // Dropping the transaction on error rolls back everything written so far
+        let tx = conn.transaction()?;
+        // The batch is checked as a whole against the usage it started from
+        let usage = if quotas.is_unlimited() {
+            UsageReport::default()
+        } else {
+            Self::read_usage(&tx)?
+        };
+        let mut changes = Vec::new();
+        let mut report = LoadReport::default();
+        for resource in resources {
+            let existing = Self::owner_and_size(&tx, &resource.path)?;
+
+            match (existing, policy) {
+                (None, _) => {
+                    Self::check_namespace(&tx, &resource.path)?;
+                    self.insert_resource(&tx, resource)?;
+                    changes.push(UsageChange::created(resource));
+                    report.created += 1;
+                }
+                (Some((old_user_id, old_size)), ConflictPolicy::Overwrite) => {
+                    let old_hash: Option<String> = tx.query_row(
+                        "SELECT content_hash FROM resources WHERE path = ?1",
+                        [&resource.path],
+                        |row| row.get(0),
+                    )?;
+                    let hash = self.store_content(&tx, resource.content.as_deref())?;
+                    tx.execute(
+                        "UPDATE resources SET user_id = ?1, content_hash = ?2, size = ?3, created_at = ?4, updated_at = ?5
+                         WHERE path = ?6",
+                        params![
+                            resource.user_id,
+                            hash,
+                            resource.size,
+                            resource.created_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                            resource.updated_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                            resource.path,
+                        ],
+                    )?;
+                    Self::release_content(&tx, old_hash.as_deref())?;
+                    Self::charge_usage(&tx, old_user_id, &resource.path, -old_size, -1)?;
+                    Self::charge_usage(&tx, resource.user_id, &resource.path, resource.size, 1)?;
+                    changes.push(UsageChange {
+                        user_id: old_user_id,
+                        path: resource.path.clone(),
+                        bytes: -old_size,
+                        resources: -1,
+                    });
+                    changes.push(UsageChange::created(resource));
+                    report.overwritten += 1;
+                }
+                (Some(_), ConflictPolicy::Skip) => report.skipped += 1,
+                (Some(_), ConflictPolicy::Fail) => {
+                    return Err(StoreError::Conflict(format!("Resource {}", resource.path)));
+                }
+            }
+        }
+        check_quotas(quotas, &changes, || Ok(usage))?;
+        tx.commit()?;
 
-    fn row_to_resource(&self, row: &Row) -> Result<Resource> {
-        // Get values with explicit error handling
-        let id: i64 = row.get(0).map_err(|e| anyhow!("Failed to get id: {}", e))?;
-        let user_id: i64 = row.get(1).map_err(|e| anyhow!("Failed to get user_id: {}", e))?;
-        let path: String = row.get(2).map_err(|e| anyhow!("Failed to get path: {}", e))?;
-        let content: Option<String> = row.get(3).map_err(|e| anyhow!("Failed to get content: {}", e))?;
-        let size: i64 = row.get(4).map_err(|e| anyhow!("Failed to get size: {}", e))?;
-        let created_at_str: String = row.get(5).map_err(|e| anyhow!("Failed to get created_at: {}", e))?;
-        let updated_at_str: String = row.get(6).map_err(|e| anyhow!("Failed to get updated_at: {}", e))?;
+        Ok(report)
+    }
+
+    fn ping(&self) -> StoreResult<()> {
+        let conn = self.lock()?;
+        conn.query_row("SELECT 1", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    fn schema_problems(&self) -> StoreResult<Vec<String>> {
+        self.schema_drift()
+    }
+
+    fn folder_stats(&self, folder_path: &str) -> StoreResult<Option<FolderStats>> {
+        let conn = self.lock()?;
+
+        let folder = folder_path.trim_end_matches('/');
+        let prefix = format!("{}/", folder);
+
+        let (resources, bytes, oldest, newest) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0), MIN(updated_at), MAX(updated_at)
+             FROM resources WHERE substr(path, 1, length(?1)) = ?1",
+            params![prefix],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            },
+        )?;
+
+        if resources == 0 && !folder.is_empty() {
+            return Ok(None);
+        }
+
+        // Walk every resource's ancestors up to the folder itself; the
+        // `rtrim(x, replace(x, '/', ''))` idiom strips the last path segment
+        let folders: i64 = conn.query_row(
+            "WITH RECURSIVE dirs(dir) AS (
+                 SELECT rtrim(rtrim(path, replace(path, '/', '')), '/')
+                 FROM resources WHERE substr(path, 1, length(?1)) = ?1
+                 UNION
+                 SELECT rtrim(rtrim(dir, replace(dir, '/', '')), '/')
+                 FROM dirs WHERE length(dir) > length(?2)
+             )
+             SELECT COUNT(*) FROM dirs WHERE length(dir) > length(?2)",
+            params![prefix, folder],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT path, COALESCE(size, 0) FROM resources
+             WHERE substr(path, 1, length(?1)) = ?1
+             ORDER BY size DESC, path LIMIT ?2",
+        )?;
+        let largest = stmt
+            .query_map(params![prefix, LARGEST_RESOURCES as i64], |row| {
+                Ok(ResourceSize {
+                    path: row.get(0)?,
+                    size: row.get(1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Timestamps are stored in one fixed RFC 3339 format, so MIN and MAX order them correctly
+        Ok(Some(FolderStats {
+            path: folder_path.to_string(),
+            resources,
+            bytes,
+            folders,
+            largest,
+            oldest_updated_at: oldest.and_then(|t| self.parse_datetime(&t).ok()),
+            newest_updated_at: newest.and_then(|t| self.parse_datetime(&t).ok()),
+        }))
+    }
+
+    fn usage(&self) -> StoreResult<UsageReport> {
+        let conn = self.lock()?;
+
+        Self::read_usage(&conn)
+    }
+
+    fn supports_snapshots(&self) -> bool {
+        true
+    }
+
+    fn create_snapshot(&self, name: &str) -> StoreResult<SnapshotInfo> {
+        validate_snapshot_name(name)?;
+        fs::create_dir_all(&self.snapshot_dir)?;
+
+        let target = self.snapshot_path(name);
+        if target.exists() {
+            return Err(StoreError::Conflict(format!("Snapshot {}", name)));
+        }
+
+        // Back up into a temporary file first so a half-written snapshot is never listed
+        let partial = self.snapshot_dir.join(format!(".{}.partial", name));
+        {
+            let conn = self.lock()?;
+            conn.backup(MAIN_DB, &partial, None)?;
+        }
+        fs::rename(&partial, &target)?;
 
-        Ok(Resource {
+        Self::snapshot_info(name, &target)
+    }
+
+    fn list_snapshots(&self) -> StoreResult<Vec<SnapshotInfo>> {
+        let mut snapshots = Vec::new();
+        if !self.snapshot_dir.is_dir() {
+            return Ok(snapshots);
+        }
+
+        for entry in fs::read_dir(&self.snapshot_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("sqlite") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                snapshots.push(Self::snapshot_info(name, &path)?);
+            }
+        }
+
+        snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(snapshots)
+    }
+
+    fn restore_snapshot(&self, name: &str) -> StoreResult<()> {
+        validate_snapshot_name(name)?;
+
+        let source = self.snapshot_path(name);
+        if !source.is_file() {
+            return Err(StoreError::NotFound(format!("Snapshot {}", name)));
+        }
+
+        // Holding the connection lock for the whole swap blocks every other
+        // request, so nobody observes a partially restored database
+        let mut conn = self.lock()?;
+        let audit_entries = Self::read_audit_entries(&conn, &AuditFilter::default())?;
+        conn.restore(MAIN_DB, &source, None::<fn(Progress)>)?;
+
+        // Snapshots taken by older versions may need migrating
+        run_migrations(&mut conn, None, false).map_err(schema_error)?;
+
+        // The audit log records history, restoring older data must not roll it back
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM audit_log", [])?;
+        for entry in &audit_entries {
+            Self::insert_audit_entry(&tx, entry)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn supports_audit(&self) -> bool {
+        true
+    }
+
+    fn record_audit(&self, event: &AuditEvent) -> StoreResult<AuditEntry> {
+        let mut conn = self.lock()?;
+
+        let tx = conn.transaction()?;
+        let last: Option<(i64, String)> = tx
+            .query_row(
+                "SELECT id, hash FROM audit_log ORDER BY id DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let (id, prev_hash) = match last {
+            Some((id, hash)) => (id + 1, hash),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+
+        // Stored with millisecond precision, which is what the hash covers
+        let timestamp = Utc::now().trunc_subsecs(3);
+        let entry = AuditEntry::chain(id, timestamp, &prev_hash, event);
+        Self::insert_audit_entry(&tx, &entry)?;
+        tx.commit()?;
+
+        Ok(entry)
+    }
+
+    fn audit_entries(&self, filter: &AuditFilter) -> StoreResult<Vec<AuditEntry>> {
+        let conn = self.lock()?;
+
+        Self::read_audit_entries(&conn, filter)
+    }
+
+    fn verify_audit(&self) -> StoreResult<Vec<String>> {
+        let conn = self.lock()?;
+
+        Ok(verify_chain(&Self::read_audit_entries(&conn, &AuditFilter::default())?))
+    }
+
+    fn supports_folder_policies(&self) -> bool {
+        true
+    }
+
+    fn folder_policies(&self) -> StoreResult<Vec<FolderPolicy>> {
+        let conn = self.lock()?;
+
+        Self::read_policies(&conn)
+    }
+
+    fn set_folder_policy(&self, policy: &FolderPolicy) -> StoreResult<()> {
+        let conn = self.lock()?;
+
+        let content_types = serde_json::to_string(&policy.content_types)
+            .map_err(|e| StoreError::Corrupt(e.to_string()))?;
+        let json_schema = policy.json_schema.as_ref().map(|schema| schema.to_string());
+        conn.execute(
+            "INSERT OR REPLACE INTO folder_policies
+                 (folder, max_folder_depth, max_resource_size, name_pattern, content_types, read_only, json_schema)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                policy.folder,
+                policy.max_folder_depth.map(|n| n as i64),
+                policy.max_resource_size.map(|n| n as i64),
+                policy.name_pattern,
+                content_types,
+                policy.read_only,
+                json_schema,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn remove_folder_policy(&self, folder: &str) -> StoreResult<FolderPolicy> {
+        let mut conn = self.lock()?;
+
+        let tx = conn.transaction()?;
+        let policy = {
+            let mut stmt = tx.prepare_cached(
+                "SELECT folder, max_folder_depth, max_resource_size, name_pattern, content_types, read_only,
+                        json_schema
+                 FROM folder_policies WHERE folder = ?1",
+            )?;
+            let mut rows = stmt.query([folder])?;
+            match rows.next()? {
+                Some(row) => Self::row_to_policy(row)?,
+                None => return Err(StoreError::NotFound(format!("Policy for {}", folder))),
+            }
+        };
+        tx.execute("DELETE FROM folder_policies WHERE folder = ?1", [folder])?;
+        tx.commit()?;
+
+        Ok(policy)
+    }
+}
+
+impl Database {
+    fn find_resource(&self, conn: &Connection, path: &str) -> StoreResult<Option<Resource>> {
+        Ok(self.find_stored(conn, path, false)?.map(|(resource, _)| resource))
+    }
+
+    /// Looks up `path`. With `keep_gzip` a compressed body is returned as
+    /// stored, in the second element, instead of being decoded into `content`.
+    fn find_stored(
+        &self,
+        conn: &Connection,
+        path: &str,
+        keep_gzip: bool,
+    ) -> StoreResult<Option<(Resource, Option<Vec<u8>>)>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT r.id, r.user_id, r.path, r.size, r.created_at, r.updated_at, r.content_hash, c.encoding, c.data
+             FROM resources r LEFT JOIN contents c ON c.hash = r.content_hash
+             WHERE r.path = ?1",
+        )?;
+
+        let mut rows = stmt.query(params![path])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(self.row_to_resource(row, keep_gzip)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn row_to_resource(&self, row: &Row, keep_gzip: bool) -> StoreResult<(Resource, Option<Vec<u8>>)> {
+        let id: i64 = row.get(0)?;
+        let user_id: i64 = row.get(1)?;
+        let path: String = row.get(2)?;
+        let size: i64 = row.get(3)?;
+        let created_at_str: String = row.get(4)?;
+        let updated_at_str: String = row.get(5)?;
+        let content_hash: Option<String> = row.get(6)?;
+        let encoding: Option<String> = row.get(7)?;
+        let data: Option<Vec<u8>> = row.get(8)?;
+
+        let (content, gzipped) = match (encoding.as_deref(), data) {
+            (Some(GZIP), Some(data)) if keep_gzip => (None, Some(data)),
+            (Some(encoding), Some(data)) => {
+                let content = decode_content(encoding, &data)
+                    .map_err(|e| StoreError::Corrupt(format!("{}: {}", path, e)))?;
+                (Some(content), None)
+            }
+            _ if content_hash.is_some() => {
+                return Err(StoreError::Corrupt(format!("{}: stored content is missing", path)));
+            }
+            _ => (None, None),
+        };
+
+        let resource = Resource {
             id: Some(id),
             user_id,
             path,
             content,
+            content_hash,
             size,
             created_at: self.parse_datetime(&created_at_str)?,
             updated_at: self.parse_datetime(&updated_at_str)?,
+        };
+
+        Ok((resource, gzipped))
+    }
+
+    fn insert_resource(&self, conn: &Connection, resource: &Resource) -> StoreResult<i64> {
+        let hash = self.store_content(conn, resource.content.as_deref())?;
+        Self::insert_row(conn, resource, hash.as_deref())
+    }
+
+    /// Inserts the row for `resource` pointing at content already stored
+    /// under `hash`, whose reference the row takes over.
+    fn insert_row(conn: &Connection, resource: &Resource, hash: Option<&str>) -> StoreResult<i64> {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO resources (user_id, path, content_hash, size, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+
+        let id = stmt.insert(params![
+            resource.user_id,
+            resource.path,
+            hash,
+            resource.size,
+            resource
+                .created_at
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .to_string(),
+            resource
+                .updated_at
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+                .to_string(),
+        ])?;
+        Self::charge_usage(conn, resource.user_id, &resource.path, resource.size, 1)?;
+
+        Ok(id)
+    }
+
+    /// Adds a reference to `content`, storing it if no resource has it yet.
+    /// Returns the hash the referencing row must point at.
+    fn store_content(&self, conn: &Connection, content: Option<&str>) -> StoreResult<Option<String>> {
+        let Some(content) = content else {
+            return Ok(None);
+        };
+
+        let hash = content_hash(content);
+        let shared = conn
+            .prepare_cached("UPDATE contents SET refcount = refcount + 1 WHERE hash = ?1")?
+            .execute([&hash])?;
+        if shared == 0 {
+            // Only new content is worth compressing
+            let (encoding, data) = encode_content(content, self.compression_min_bytes)?;
+            conn.prepare_cached("INSERT INTO contents (hash, encoding, data, refcount) VALUES (?1, ?2, ?3, 1)")?
+                .execute(params![hash, encoding, data])?;
+        }
+
+        Ok(Some(hash))
+    }
+
+    /// Drops a reference to the content stored under `hash`, deleting it
+    /// once nothing refers to it.
+    fn release_content(conn: &Connection, hash: Option<&str>) -> StoreResult<()> {
+        let Some(hash) = hash else {
+            return Ok(());
+        };
+
+        conn.prepare_cached("UPDATE contents SET refcount = refcount - 1 WHERE hash = ?1")?
+            .execute([hash])?;
+        conn.prepare_cached("DELETE FROM contents WHERE hash = ?1 AND refcount <= 0")?
+            .execute([hash])?;
+
+        Ok(())
+    }
+
+    fn owner_and_size(conn: &Connection, path: &str) -> StoreResult<Option<(i64, i64)>> {
+        Ok(conn
+            .query_row(
+                "SELECT user_id, COALESCE(size, 0) FROM resources WHERE path = ?1",
+                params![path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?)
+    }
+
+    /// Refuses to create `path` if one of its parent folders is a resource or
+    /// resources already exist below it. The root is always a folder.
+    fn check_namespace(conn: &Connection, path: &str) -> StoreResult<()> {
+        if path == "/" {
+            return Err(StoreError::Conflict("Folder /".to_string()));
+        }
+
+        let parents = parent_folders(path);
+        if !parents.is_empty() {
+            let sql = format!(
+                "SELECT path FROM resources WHERE path IN ({}) LIMIT 1",
+                vec!["?"; parents.len()].join(", ")
+            );
+            let parent: Option<String> = conn
+                .query_row(&sql, params_from_iter(&parents), |row| row.get(0))
+                .optional()?;
+            if let Some(parent) = parent {
+                return Err(StoreError::Conflict(format!("Resource {}", parent)));
+            }
+        }
+
+        // Paths below `path` sort between "<path>/" and "<path>0", which lets
+        // SQLite answer from the index on `path`
+        let has_children: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM resources WHERE path > ?1 AND path < ?2)",
+            params![format!("{}/", path), format!("{}0", path)],
+            |row| row.get(0),
+        )?;
+        if has_children {
+            return Err(StoreError::Conflict(format!("Folder {}", path)));
+        }
+
+        Ok(())
+    }
+
+    /// Adds to the running totals in the `usage` table, which must change in
+    /// the same transaction as the resource rows they describe.
+    fn charge_usage(conn: &Connection, user_id: i64, path: &str, bytes: i64, resources: i64) -> StoreResult<()> {
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO usage (scope, key, bytes, resources) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(scope, key) DO UPDATE SET
+                 bytes = bytes + excluded.bytes,
+                 resources = resources + excluded.resources",
+        )?;
+        stmt.execute(params!["user", user_id.to_string(), bytes, resources])?;
+        stmt.execute(params!["folder", top_level_folder(path), bytes, resources])?;
+
+        if resources < 0 {
+            conn.execute("DELETE FROM usage WHERE resources <= 0", [])?;
+        }
+
+        Ok(())
+    }
+
+    fn read_policies(conn: &Connection) -> StoreResult<Vec<FolderPolicy>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT folder, max_folder_depth, max_resource_size, name_pattern, content_types, read_only, json_schema
+             FROM folder_policies ORDER BY folder",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut policies = Vec::new();
+        while let Some(row) = rows.next()? {
+            policies.push(Self::row_to_policy(row)?);
+        }
+
+        Ok(policies)
+    }
+
+    fn read_usage(conn: &Connection) -> StoreResult<UsageReport> {
+        let mut report = UsageReport::default();
+        let mut stmt = conn.prepare("SELECT scope, key, bytes, resources FROM usage")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?;
+
+        for row in rows {
+            let (scope, key, bytes, resources) = row?;
+            let usage = Usage { bytes, resources };
+            match scope.as_str() {
+                "user" => {
+                    let user_id = key
+                        .parse()
+                        .map_err(|_| StoreError::Corrupt(format!("Invalid user id in usage table: {}", key)))?;
+                    report.users.insert(user_id, usage);
+                }
+                "folder" => {
+                    report.folders.insert(key, usage);
+                }
+                _ => return Err(StoreError::Corrupt(format!("Unknown usage scope: {}", scope))),
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn insert_audit_entry(conn: &Connection, entry: &AuditEntry) -> StoreResult<()> {
+        conn.execute(
+            "INSERT INTO audit_log (id, timestamp, user_id, client_ip, method, path, outcome,
+                                    old_size, new_size, content_hash, prev_hash, hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                entry.id,
+                entry.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+                entry.user_id,
+                entry.client_ip,
+                entry.method,
+                entry.path,
+                entry.outcome,
+                entry.old_size,
+                entry.new_size,
+                entry.content_hash,
+                entry.prev_hash,
+                entry.hash,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    fn read_audit_entries(conn: &Connection, filter: &AuditFilter) -> StoreResult<Vec<AuditEntry>> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(under) = filter.under.as_deref().filter(|under| *under != "/") {
+            values.push(Value::Text(under.to_string()));
+            values.push(Value::Text(format!("{}/", under)));
+            conditions.push(format!(
+                "(path = ?{} OR substr(path, 1, length(?{n})) = ?{n})",
+                values.len() - 1,
+                n = values.len()
+            ));
+        }
+        if let Some(since) = filter.since {
+            values.push(Value::Text(since.to_rfc3339_opts(SecondsFormat::Millis, true)));
+            conditions.push(format!("timestamp >= ?{}", values.len()));
+        }
+        if let Some(user_id) = filter.user_id {
+            values.push(Value::Integer(user_id));
+            conditions.push(format!("user_id = ?{}", values.len()));
+        }
+
+        let mut sql = "SELECT id, timestamp, user_id, client_ip, method, path, outcome,
+                              old_size, new_size, content_hash, prev_hash, hash
+                       FROM audit_log"
+            .to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY id");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok((
+                AuditEntry {
+                    id: row.get(0)?,
+                    timestamp: DateTime::<Utc>::MIN_UTC,
+                    user_id: row.get(2)?,
+                    client_ip: row.get(3)?,
+                    method: row.get(4)?,
+                    path: row.get(5)?,
+                    outcome: row.get(6)?,
+                    old_size: row.get(7)?,
+                    new_size: row.get(8)?,
+                    content_hash: row.get(9)?,
+                    prev_hash: row.get(10)?,
+                    hash: row.get(11)?,
+                },
+                row.get::<_, String>(1)?,
+            ))
+        })?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let (mut entry, timestamp) = row?;
+            entry.timestamp = DateTime::parse_from_rfc3339(&timestamp)
+                .map_err(|e| {
+                    StoreError::Corrupt(format!("audit entry {}: invalid timestamp {:?}: {}", entry.id, timestamp, e))
+                })?
+                .with_timezone(&Utc);
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    fn row_to_policy(row: &Row) -> StoreResult<FolderPolicy> {
+        let folder: String = row.get(0)?;
+        let content_types: String = row.get(4)?;
+        let content_types = serde_json::from_str(&content_types).map_err(|e| {
+            StoreError::Corrupt(format!("policy for {}: invalid content types {:?}: {}", folder, content_types, e))
+        })?;
+        let json_schema = match row.get::<_, Option<String>>(6)? {
+            None => None,
+            Some(schema) => Some(serde_json::from_str(&schema).map_err(|e| {
+                StoreError::Corrupt(format!("policy for {}: invalid JSON Schema: {}", folder, e))
+            })?),
+        };
+
+        Ok(FolderPolicy {
+            max_folder_depth: row.get::<_, Option<i64>>(1)?.map(|n| n.max(0) as usize),
+            max_resource_size: row.get::<_, Option<i64>>(2)?.map(|n| n.max(0) as usize),
+            name_pattern: row.get(3)?,
+            content_types,
+            read_only: row.get(5)?,
+            json_schema,
+            folder,
         })
     }
 
-    fn parse_datetime(&self, datetime_str: &str) -> Result<DateTime<Utc>> {
+    fn snapshot_path(&self, name: &str) -> PathBuf {
+        self.snapshot_dir.join(format!("{}.sqlite", name))
+    }
+
+    fn snapshot_info(name: &str, path: &Path) -> StoreResult<SnapshotInfo> {
+        let metadata = fs::metadata(path)?;
+        Ok(SnapshotInfo {
+            name: name.to_string(),
+            size: metadata.len(),
+            created_at: metadata.modified()?.into(),
+        })
+    }
+
+    fn parse_datetime(&self, datetime_str: &str) -> StoreResult<DateTime<Utc>> {
         match DateTime::parse_from_rfc3339(datetime_str) {
             Ok(dt) => Ok(dt.with_timezone(&Utc)),
-            Err(e) => Err(anyhow!("Failed to parse RFC3339 datetime: {}", e)),
+            Err(e) => Err(StoreError::Corrupt(format!("Invalid timestamp {:?}: {}", datetime_str, e))),
+        }
+    }
+}
+
+/// `contents.encoding` of bodies stored as they are.
+const IDENTITY: &str = "identity";
+/// `contents.encoding` of gzip-compressed bodies.
+const GZIP: &str = "gzip";
+
+/// Compresses `content` if it is at least `min_bytes` long and shrinks by
+/// doing so. Returns the encoding and the bytes to store.
+fn encode_content(content: &str, min_bytes: Option<usize>) -> StoreResult<(&'static str, Vec<u8>)> {
+    if min_bytes.is_some_and(|min| content.len() >= min) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes())?;
+        let compressed = encoder.finish()?;
+        if compressed.len() < content.len() {
+            return Ok((GZIP, compressed));
         }
     }
+
+    Ok((IDENTITY, content.as_bytes().to_vec()))
+}
+
+fn decode_content(encoding: &str, data: &[u8]) -> Result<String, String> {
+    match encoding {
+        IDENTITY => String::from_utf8(data.to_vec()).map_err(|e| format!("stored content is not UTF-8: {}", e)),
+        GZIP => {
+            let mut content = String::new();
+            GzDecoder::new(data)
+                .read_to_string(&mut content)
+                .map_err(|e| format!("cannot decompress stored content: {}", e))?;
+            Ok(content)
+        }
+        other => Err(format!("unknown content encoding {:?}", other)),
+    }
+}
+
+/// Keeps SQLite errors from the migration helpers typed; anything else they
+/// report means the stored schema cannot be brought up to date.
+fn schema_error(e: anyhow::Error) -> StoreError {
+    match e.downcast::<rusqlite::Error>() {
+        Ok(e) => e.into(),
+        Err(e) => StoreError::Corrupt(e.to_string()),
+    }
 }
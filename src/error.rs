@@ -0,0 +1,182 @@
+use crate::store::StoreError;
+use crate::validation::{ValidationError, MAX_UPLOAD_SIZE};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse, ResponseError};
+use serde_json::json;
+
+/// Media type of every error body.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Every failure a handler reports to the client, rendered as an RFC 7807
+/// problem document with a stable `code`.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    /// Malformed request other than an invalid path or content, e.g. an
+    /// unknown query value or an unreadable archive.
+    #[error("{detail}")]
+    BadRequest { code: &'static str, detail: String },
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    AlreadyExists(String),
+    #[error("{0}")]
+    FolderNotEmpty(String),
+    #[error("{0}")]
+    QuotaExceeded(String),
+    #[error("{0}")]
+    NotSupported(String),
+    /// Request body in a `Content-Encoding` the server cannot decode.
+    #[error("{0}")]
+    UnsupportedEncoding(String),
+    /// Storage or other server-side failure. The cause is only logged, the
+    /// client gets a generic message.
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::BadRequest {
+            code,
+            detail: detail.into(),
+        }
+    }
+
+    /// Stable identifier clients can match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation(e) => e.code(),
+            Self::BadRequest { code, .. } => code,
+            Self::NotFound(_) => "not_found",
+            Self::AlreadyExists(_) => "already_exists",
+            Self::FolderNotEmpty(_) => "folder_not_empty",
+            Self::QuotaExceeded(_) => "quota_exceeded",
+            Self::NotSupported(_) => "not_supported",
+            Self::UnsupportedEncoding(_) => "unsupported_content_encoding",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Validation(e) => e.title(),
+            Self::BadRequest { .. } => "Bad request",
+            Self::NotFound(_) => "Not found",
+            Self::AlreadyExists(_) => "Already exists",
+            Self::FolderNotEmpty(_) => "Folder not empty",
+            Self::QuotaExceeded(_) => "Quota exceeded",
+            Self::NotSupported(_) => "Not supported",
+            Self::UnsupportedEncoding(_) => "Unsupported encoding",
+            Self::Internal(_) => "Internal error",
+        }
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            Self::Internal(_) => "The server failed to complete the request".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::NotFound(_) => Self::NotFound(e.to_string()),
+            StoreError::Conflict(_) => Self::AlreadyExists(e.to_string()),
+            StoreError::NotEmpty(_) => Self::FolderNotEmpty(e.to_string()),
+            StoreError::Invalid(e) => Self::Validation(e),
+            StoreError::Unsupported(_) => Self::NotSupported(e.to_string()),
+            StoreError::QuotaExceeded(message) => Self::QuotaExceeded(message),
+            other => Self::Internal(other.into()),
+        }
+    }
+}
+
+impl From<&PayloadError> for ApiError {
+    fn from(e: &PayloadError) -> Self {
+        match e {
+            PayloadError::Overflow => Self::Validation(ValidationError::ContentTooLarge(MAX_UPLOAD_SIZE)),
+            other => Self::bad_request("invalid_body", other.to_string()),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Validation(ValidationError::ReadOnly(_)) => StatusCode::FORBIDDEN,
+            Self::Validation(ValidationError::ContentTypeNotAllowed(_)) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Validation(ValidationError::SchemaViolations(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Validation(_) | Self::BadRequest { .. } | Self::FolderNotEmpty(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::AlreadyExists(_) => StatusCode::CONFLICT,
+            Self::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+            Self::NotSupported(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let mut problem = json!({
+            "type": format!("urn:gresources:problem:{}", self.code()),
+            "title": self.title(),
+            "status": status.as_u16(),
+            "detail": self.detail(),
+            "code": self.code(),
+        });
+        // Clients fixing a document need every violation, not just a count
+        if let Self::Validation(ValidationError::SchemaViolations(violations)) = self {
+            problem["violations"] = json!(violations);
+        }
+
+        HttpResponse::build(status)
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .body(problem.to_string())
+    }
+}
+
+/// Query string extractor configuration reporting malformed parameters as
+/// problem documents instead of actix's plain-text errors.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|e, _| ApiError::bad_request("invalid_query", e.to_string()).into())
+}
+
+/// Request body configuration limiting uploads to [`MAX_UPLOAD_SIZE`].
+/// `PayloadConfig` takes no error handler, so `payload_problems` must be
+/// registered with it to report its errors as problem documents.
+pub fn payload_config() -> web::PayloadConfig {
+    web::PayloadConfig::new(MAX_UPLOAD_SIZE)
+}
+
+/// Middleware rendering the errors of actix's body extractors, e.g. a body
+/// over the `payload_config` limit, as problem documents instead of plain text.
+pub async fn payload_problems(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let response = next.call(req).await?;
+    let problem = response
+        .response()
+        .error()
+        .and_then(|e| e.as_error::<PayloadError>())
+        .map(ApiError::from);
+
+    Ok(match problem {
+        Some(problem) => response
+            .into_response(problem.error_response())
+            .map_into_right_body(),
+        None => response.map_into_left_body(),
+    })
+}
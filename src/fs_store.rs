@@ -0,0 +1,407 @@
+use crate::audit::content_hash;
+use crate::models::{parent_folders, FolderInfo, Resource, DEFAULT_USER_ID};
+use crate::quota::{Quotas, UsageChange};
+use crate::store::{check_quotas, ResourceStore, StoreError, StoreResult};
+use crate::validation::{
+    validate_content_with_limits, validate_path_with_limits, ValidationError, ValidationLimits,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Directory under the storage root that holds resource metadata.
+/// It mirrors the resource tree so the resource files themselves stay untouched.
+const METADATA_DIR: &str = ".gresources";
+const NEXT_ID_FILE: &str = "next_id";
+/// Files are written here first and renamed into place, so readers never see
+/// a partial write. It is on the same filesystem as the resources.
+const TMP_DIR: &str = "tmp";
+
+/// Distinguishes temporary files written by concurrent stores in one process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Store serving resources straight from a directory tree.
+///
+/// Resources are plain files and folders are directories, so fixtures can be
+/// edited in place. Ids and timestamps live in TOML sidecars under
+/// `<root>/.gresources/meta`; files created by hand without a sidecar get
+/// their timestamps from the filesystem. Symbolic links are never followed,
+/// so nothing outside the root can be read or written through the store.
+pub struct FsStore {
+    root: PathBuf,
+    limits: ValidationLimits,
+    write_lock: Mutex<()>,
+}
+
+/// What a resource path points at on disk.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    Missing,
+    File,
+    Folder,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    id: i64,
+    user_id: i64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl FsStore {
+    pub fn new(root: impl Into<PathBuf>, limits: ValidationLimits) -> StoreResult<Self> {
+        let root = root.into();
+        fs::create_dir_all(root.join(METADATA_DIR).join("meta"))?;
+        fs::create_dir_all(root.join(METADATA_DIR).join(TMP_DIR))?;
+
+        Ok(Self {
+            root,
+            limits,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    fn lock(&self) -> StoreResult<std::sync::MutexGuard<'_, ()>> {
+        self.write_lock
+            .lock()
+            .map_err(|_| StoreError::LockPoisoned("filesystem store"))
+    }
+
+    /// Maps a resource path onto a relative filesystem path, rejecting
+    /// anything that could escape the storage root.
+    fn relative_path(path: &str) -> StoreResult<PathBuf> {
+        let mut relative = PathBuf::new();
+
+        for (index, segment) in path.split('/').filter(|s| !s.is_empty()).enumerate() {
+            if segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(ValidationError::InvalidCharacters.into());
+            }
+            if index == 0 && segment == METADATA_DIR {
+                return Err(ValidationError::ReservedPath(METADATA_DIR.to_string()).into());
+            }
+            relative.push(segment);
+        }
+
+        Ok(relative)
+    }
+
+    /// Finds the file for a resource path and what is there, checking every
+    /// component with `symlink_metadata` so that links are refused rather than
+    /// followed. A path below a file is missing.
+    fn entry(&self, path: &str) -> StoreResult<(PathBuf, Entry)> {
+        let relative = Self::relative_path(path)?;
+        let file = self.root.join(&relative);
+
+        let mut current = self.root.clone();
+        let mut entry = Entry::Folder;
+        for segment in relative.iter() {
+            if entry == Entry::File {
+                return Ok((file, Entry::Missing));
+            }
+            current.push(segment);
+            let file_type = match fs::symlink_metadata(&current) {
+                Ok(metadata) => metadata.file_type(),
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok((file, Entry::Missing)),
+                Err(e) => return Err(e.into()),
+            };
+            entry = if file_type.is_dir() {
+                Entry::Folder
+            } else if file_type.is_file() {
+                Entry::File
+            } else if file_type.is_symlink() {
+                return Err(StoreError::Corrupt(format!("{} is a symbolic link", current.display())));
+            } else {
+                return Err(StoreError::Corrupt(format!("{} is not a regular file", current.display())));
+            };
+        }
+
+        Ok((file, entry))
+    }
+
+    /// Replaces `file` with `contents` in one step: the contents go to a
+    /// temporary file that is then renamed over the target.
+    fn write_atomically(&self, file: &Path, contents: &[u8]) -> StoreResult<()> {
+        let tmp = self.root.join(METADATA_DIR).join(TMP_DIR).join(format!(
+            "{}-{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = fs::write(&tmp, contents).and_then(|_| fs::rename(&tmp, file));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        Ok(result?)
+    }
+
+    fn metadata_file(&self, path: &str) -> StoreResult<PathBuf> {
+        let mut file = self
+            .root
+            .join(METADATA_DIR)
+            .join("meta")
+            .join(Self::relative_path(path)?)
+            .into_os_string();
+        file.push(".toml");
+        Ok(PathBuf::from(file))
+    }
+
+    fn read_metadata(&self, path: &str, file: &Path) -> StoreResult<Metadata> {
+        let metadata_file = self.metadata_file(path)?;
+        match fs::read_to_string(&metadata_file) {
+            Ok(contents) => {
+                return toml::from_str(&contents)
+                    .map_err(|e| StoreError::Corrupt(format!("{}: {}", metadata_file.display(), e)))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // Hand-made file without a sidecar, derive what we can from the filesystem
+        let fs_metadata = fs::symlink_metadata(file)?;
+        let updated_at: DateTime<Utc> = fs_metadata.modified()?.into();
+        let created_at: DateTime<Utc> = fs_metadata
+            .created()
+            .map(Into::into)
+            .unwrap_or(updated_at);
+
+        Ok(Metadata {
+            id: 0,
+            user_id: DEFAULT_USER_ID,
+            created_at,
+            updated_at,
+        })
+    }
+
+    fn write_metadata(&self, path: &str, metadata: &Metadata) -> StoreResult<()> {
+        let metadata_file = self.metadata_file(path)?;
+        if let Some(parent) = metadata_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(metadata).map_err(|e| StoreError::Corrupt(e.to_string()))?;
+        self.write_atomically(&metadata_file, contents.as_bytes())
+    }
+
+    fn read_resource(&self, path: &str, file: &Path) -> StoreResult<Resource> {
+        let content = fs::read_to_string(file)?;
+        let metadata = self.read_metadata(path, file)?;
+
+        Ok(Resource {
+            id: Some(metadata.id),
+            user_id: metadata.user_id,
+            path: path.to_string(),
+            size: content.len() as i64,
+            content_hash: Some(content_hash(&content)),
+            content: Some(content),
+            created_at: metadata.created_at,
+            updated_at: metadata.updated_at,
+        })
+    }
+
+    fn next_id(&self) -> StoreResult<i64> {
+        let id_file = self.root.join(METADATA_DIR).join(NEXT_ID_FILE);
+        let id = match fs::read_to_string(&id_file) {
+            Ok(contents) => contents
+                .trim()
+                .parse::<i64>()
+                .map_err(|e| StoreError::Corrupt(format!("{}: {}", id_file.display(), e)))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => 1,
+            Err(e) => return Err(e.into()),
+        };
+        self.write_atomically(&id_file, (id + 1).to_string().as_bytes())?;
+        Ok(id)
+    }
+
+    /// Removes directories left empty by a delete, up to (but excluding) the root,
+    /// so folders disappear with their last resource like in the other backends.
+    fn prune_empty_dirs(&self, mut dir: PathBuf, meta: bool) {
+        let stop = if meta {
+            self.root.join(METADATA_DIR).join("meta")
+        } else {
+            self.root.clone()
+        };
+
+        while dir != stop && dir.starts_with(&stop) {
+            if fs::remove_dir(&dir).is_err() {
+                break;
+            }
+            if !dir.pop() {
+                break;
+            }
+        }
+    }
+
+    /// Recursively collects resource paths below `dir`, which maps to `prefix`.
+    fn collect_files(&self, dir: &Path, prefix: &str, out: &mut Vec<String>) -> StoreResult<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if prefix.is_empty() && name == METADATA_DIR {
+                continue;
+            }
+
+            let path = format!("{}/{}", prefix, name);
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.collect_files(&entry.path(), &path, out)?;
+            } else if file_type.is_file() {
+                out.push(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ResourceStore for FsStore {
+    fn create_resource(&self, resource: &Resource, quotas: &Quotas) -> StoreResult<i64> {
+        validate_path_with_limits(&resource.path, &self.limits)?;
+        validate_content_with_limits(resource.content.as_deref().unwrap_or_default(), &self.limits)?;
+
+        let _guard = self.lock()?;
+        let (file, entry) = self.entry(&resource.path)?;
+
+        match entry {
+            Entry::Folder => return Err(StoreError::Conflict(format!("Folder {}", resource.path))),
+            Entry::File => return Err(StoreError::Conflict(format!("Resource {}", resource.path))),
+            Entry::Missing => {}
+        }
+        for parent in parent_folders(&resource.path) {
+            if self.entry(parent)?.1 == Entry::File {
+                return Err(StoreError::Conflict(format!("Resource {}", parent)));
+            }
+        }
+        // Reads do not take the write lock, so usage can be computed under it
+        check_quotas(quotas, &[UsageChange::created(resource)], || self.usage())?;
+
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.write_atomically(&file, resource.content.as_deref().unwrap_or_default().as_bytes())?;
+
+        let id = self.next_id()?;
+        self.write_metadata(
+            &resource.path,
+            &Metadata {
+                id,
+                user_id: resource.user_id,
+                created_at: resource.created_at,
+                updated_at: resource.updated_at,
+            },
+        )?;
+
+        Ok(id)
+    }
+
+    fn get_resource(&self, path: &str) -> StoreResult<Option<Resource>> {
+        let (file, entry) = self.entry(path)?;
+        if entry != Entry::File {
+            return Ok(None);
+        }
+
+        match self.read_resource(path, &file) {
+            // Deleted by another writer since the check above
+            Err(StoreError::Io(e)) if e.kind() == ErrorKind::NotFound => Ok(None),
+            result => result.map(Some),
+        }
+    }
+
+    fn update_resource(&self, path: &str, content: &str, quotas: &Quotas) -> StoreResult<Resource> {
+        validate_path_with_limits(path, &self.limits)?;
+        validate_content_with_limits(content, &self.limits)?;
+
+        let _guard = self.lock()?;
+        let (file, entry) = self.entry(path)?;
+        if entry != Entry::File {
+            return Err(StoreError::NotFound(format!("Resource {}", path)));
+        }
+
+        let previous = self.read_resource(path, &file)?;
+        let change = UsageChange {
+            user_id: previous.user_id,
+            path: path.to_string(),
+            bytes: content.len() as i64 - previous.size,
+            resources: 0,
+        };
+        check_quotas(quotas, &[change], || self.usage())?;
+        let mut metadata = self.read_metadata(path, &file)?;
+        self.write_atomically(&file, content.as_bytes())?;
+        metadata.updated_at = Utc::now();
+        self.write_metadata(path, &metadata)?;
+
+        Ok(previous)
+    }
+
+    fn delete_resource(&self, path: &str) -> StoreResult<Resource> {
+        let _guard = self.lock()?;
+        let (file, entry) = self.entry(path)?;
+        if entry != Entry::File {
+            return Err(StoreError::NotFound(format!("Resource {}", path)));
+        }
+
+        let deleted = self.read_resource(path, &file)?;
+        fs::remove_file(&file)?;
+        if let Some(parent) = file.parent() {
+            self.prune_empty_dirs(parent.to_path_buf(), false);
+        }
+
+        let metadata_file = self.metadata_file(path)?;
+        if metadata_file.exists() {
+            fs::remove_file(&metadata_file)?;
+        }
+        if let Some(parent) = metadata_file.parent() {
+            self.prune_empty_dirs(parent.to_path_buf(), true);
+        }
+
+        Ok(deleted)
+    }
+
+    fn list_folder_resources(&self, folder_path: &str) -> StoreResult<FolderInfo> {
+        let normalized_folder = if folder_path == "/" {
+            ""
+        } else {
+            folder_path.trim_end_matches('/')
+        };
+
+        let (dir, entry) = self.entry(normalized_folder)?;
+        if entry != Entry::Folder {
+            return Err(StoreError::NotFound(format!("Folder {}", folder_path)));
+        }
+
+        let mut resources = Vec::new();
+        self.collect_files(&dir, normalized_folder, &mut resources)?;
+        resources.sort();
+
+        let fs_metadata = fs::symlink_metadata(&dir)?;
+        let created_at: DateTime<Utc> = fs_metadata
+            .created()
+            .or_else(|_| fs_metadata.modified())?
+            .into();
+
+        Ok(FolderInfo {
+            path: folder_path.to_string(),
+            created_at,
+            resources,
+        })
+    }
+
+    fn resource_exists(&self, path: &str) -> StoreResult<bool> {
+        Ok(self.entry(path)?.1 == Entry::File)
+    }
+
+    fn folder_is_empty(&self, folder_path: &str) -> StoreResult<bool> {
+        let normalized_folder = folder_path.trim_end_matches('/');
+        let (dir, entry) = self.entry(normalized_folder)?;
+        if entry != Entry::Folder {
+            return Ok(true);
+        }
+
+        let mut resources = Vec::new();
+        self.collect_files(&dir, normalized_folder, &mut resources)?;
+        Ok(resources.is_empty())
+    }
+}
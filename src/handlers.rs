@@ -1,99 +1,197 @@
-use crate::database::Database;
-use crate::logging::Logger;
-use crate::models::Resource;
-use crate::validation::{normalize_path, validate_content, validate_path};
+use crate::archive::{import_archive, write_archive, ArchiveFormat};
+use crate::audit::AuditEvent;
+use crate::error::ApiError;
+use crate::logging::AuditLog;
+use crate::models::{ConflictPolicy, Resource};
+use crate::quota::Quotas;
+use crate::settings::Settings;
+use crate::store::{ResourceStore, StoreError};
+use crate::validation::{
+    governing_policy, normalize_path_with_limits, normalize_request_path, validate_content_with_limits,
+    validate_path_with_limits, FolderPolicy, ValidationError, ValidationLimits,
+};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::http::header::{ContentEncoding, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY};
 use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
 
 pub struct AppState {
-    pub db: Arc<Database>,
-    pub logger: Arc<Logger>,
+    pub store: Arc<dyn ResourceStore>,
+    pub audit: Arc<AuditLog>,
+    pub limits: ValidationLimits,
+    pub quotas: Quotas,
+    /// Directories whose free space `/_ready` checks.
+    pub data_dirs: Vec<PathBuf>,
+    pub min_free_disk_bytes: u64,
+}
+
+impl AppState {
+    pub fn new(store: Arc<dyn ResourceStore>, audit: Arc<AuditLog>, settings: &Settings) -> Self {
+        let mut data_dirs: Vec<PathBuf> = settings.data_directory().into_iter().collect();
+        if let Some(log_dir) = audit.sink().path().parent() {
+            if !data_dirs.iter().any(|dir| dir == log_dir) {
+                data_dirs.push(log_dir.to_path_buf());
+            }
+        }
+
+        Self {
+            store,
+            audit,
+            limits: settings.validation_limits(),
+            quotas: settings.quotas(),
+            data_dirs,
+            min_free_disk_bytes: settings.min_free_disk_bytes,
+        }
+    }
+
+    /// Records a write attempt in the audit log file and, when the backend
+    /// keeps one, in the hash-chained audit table.
+    pub fn record_write(&self, event: &AuditEvent) {
+        self.audit.log_write_operation(event);
+
+        if self.store.supports_audit() {
+            if let Err(e) = self.store.record_audit(event) {
+                error!("Failed to record audit entry for {} {}: {}", event.method, event.path, e);
+            }
+        }
+    }
+}
+
+/// Query parameters understood by the resource handlers.
+#[derive(Deserialize, Debug, Default)]
+pub struct ResourceQuery {
+    /// Archive format (`tar` or `zip`) for folder export and import.
+    pub format: Option<String>,
+    /// Present (`?stats`) to get a folder's statistics instead of its listing.
+    pub stats: Option<String>,
+    /// Path of a resource to copy instead of reading the body.
+    pub copy_from: Option<String>,
 }
 
 pub async fn handle_post(
     req: HttpRequest,
-    body: String,
+    query: web::Query<ResourceQuery>,
+    body: web::Bytes,
     data: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
-    let path = normalize_path(req.path());
-
-    data.logger.log_info(&"");
-    data.logger.log_info(&format!("POST request received for path: {}", path));
-    data.logger.log_debug(&format!("Request body length: {} bytes", body.len()));
+    let path = normalize_request_path(req.path(), &data.limits);
+    let event = AuditEvent::from_request(&req, "POST", &path);
+    check_content_encoding(&req, &data, &event)?;
 
-    // Validate path and content
-    data.logger.log_debug(&format!("Validating path: {}", path));
-    if let Err(e) = validate_path(&path) {
-        data.logger.log_warn(&format!("Path validation failed for {}: {}", path, e));
-        data.logger.log_write_operation("POST", &path, false);
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid path: {}", e)));
+    if let Some(format) = &query.format {
+        return handle_archive_import(&path, format, &body, &data, &event).await;
     }
-    data.logger.log_debug("Path validation successful");
 
-    data.logger.log_debug(&format!("Validating content (length: {})", body.len()));
-    if let Err(e) = validate_content(&body) {
-        data.logger.log_warn(&format!("Content validation failed for {}: {}", path, e));
-        data.logger.log_write_operation("POST", &path, false);
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid content: {}", e)));
+    if let Some(from) = &query.copy_from {
+        return handle_copy(&req, &path, from, &data, &event);
     }
-    data.logger.log_debug("Content validation successful");
 
-    // Check if resource already exists
-    data.logger.log_debug(&format!("Checking if resource exists: {}", path));
-    match data.db.resource_exists(&path) {
-        Ok(true) => {
-            data.logger.log_info(&format!("Resource already exists, returning conflict: {}", path));
-            data.logger.log_write_operation("POST", &path, false);
-            return Ok(HttpResponse::Conflict().body("Resource already exists"));
-        }
-        Ok(false) => {
-            data.logger.log_debug("Resource does not exist, proceeding with creation");
-        }
-        Err(e) => {
-            data.logger.log_error(&format!("Database error while checking resource existence for {}: {}", path, e));
-            data.logger.log_write_operation("POST", &path, false);
-            return Ok(HttpResponse::InternalServerError().body(format!("Database error: {}", e)));
+    let body = match String::from_utf8(body.to_vec()) {
+        Ok(body) => body,
+        Err(_) => {
+            warn!("Rejected non UTF-8 body for {}", path);
+            data.record_write(&event.failed());
+            return Err(ApiError::from(ValidationError::NotUtf8).into());
         }
+    };
+
+    info!("POST request received for path: {}", path);
+    debug!("Request body length: {} bytes", body.len());
+
+    // Validate path and content
+    debug!("Validating path: {}", path);
+    if let Err(e) = validate_path_with_limits(&path, &data.limits) {
+        warn!("Path validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
+    }
+    debug!("Path validation successful");
+
+    debug!("Validating content (length: {})", body.len());
+    if let Err(e) = validate_content_with_limits(&body, &data.limits) {
+        warn!("Content validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
     }
+    debug!("Content validation successful");
+
+    let content_type = request_content_type(&req);
+    enforce_policy(&data, &event, |policy| policy.check_write(&path, &body, Some(content_type)))?;
 
-    // Create the resource
-    data.logger.log_debug(&format!("Creating new resource: {}", path));
     let resource = Resource::new(path.clone(), body);
 
-    match data.db.create_resource(&resource) {
+    // Create the resource, the store refuses atomically if the path is taken
+    // or a quota would be exceeded
+    debug!("Creating new resource: {}", path);
+
+    match data.store.create_resource(&resource, &data.quotas) {
         Ok(_) => {
-            data.logger.log_info(&format!("Resource created successfully: {}", path));
-            data.logger.log_write_operation("POST", &path, true);
+            info!("Resource created successfully: {}", path);
+            data.record_write(&event.succeeded(None, resource.content.as_deref()));
             Ok(HttpResponse::Created().finish()) // 201 Created with no body
         }
+        Err(e @ StoreError::Conflict(_)) => {
+            // Either the path itself or a resource/folder it would collide with
+            info!("Cannot create {}, returning conflict: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+        Err(e @ StoreError::QuotaExceeded(_)) => {
+            warn!("Rejected POST {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
         Err(e) => {
-            data.logger.log_error(&format!("Failed to create resource {}: {}", path, e));
-            data.logger.log_write_operation("POST", &path, false);
-            Ok(HttpResponse::InternalServerError()
-                .body(format!("Failed to create resource: {}", e)))
+            error!("Failed to create resource {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
         }
     }
 }
 
-pub async fn handle_get(req: HttpRequest, data: web::Data<AppState>) -> ActixResult<HttpResponse> {
-    let path = normalize_path(req.path());
+pub async fn handle_get(
+    req: HttpRequest,
+    query: web::Query<ResourceQuery>,
+    data: web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let path = normalize_request_path(req.path(), &data.limits);
 
-    data.logger.log_info(&"");
-    data.logger.log_info(&format!("GET request received for path: {}", path));
+    info!("GET request received for path: {}", path);
 
-    data.logger.log_debug(&format!("Validating path: {}", path));
-    if let Err(e) = validate_path(&path) {
-        data.logger.log_warn(&format!("Path validation failed for {}: {}", path, e));
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid path: {}", e)));
+    debug!("Validating path: {}", path);
+    if let Err(e) = validate_path_with_limits(&path, &data.limits) {
+        warn!("Path validation failed for {}: {}", path, e);
+        return Err(ApiError::from(e).into());
     }
-    data.logger.log_debug("Path validation successful");
+    debug!("Path validation successful");
 
-    // Try to get resource first
-    data.logger.log_debug(&format!("Attempting to get resource: {}", path));
-    match data.db.get_resource(&path) {
-        Ok(Some(resource)) => {
-            data.logger.log_info(&format!("Resource found: {}, size: {} bytes", path, resource.size));
+    if let Some(format) = &query.format {
+        return handle_archive_export(&path, format, &data).await;
+    }
+
+    if query.stats.is_some() {
+        return handle_folder_stats(&path, &data).await;
+    }
+
+    // Try to get resource first, compressed as stored if the client takes that
+    debug!("Attempting to get resource: {}", path);
+    let found = if accepts_gzip(&req) {
+        data.store.get_resource_gzipped(&path)
+    } else {
+        data.store.get_resource(&path).map(|found| found.map(|resource| (resource, None)))
+    };
+    match found {
+        Ok(Some((resource, gzipped))) => {
+            info!("Resource found: {}, size: {} bytes", path, resource.size);
             let mut response = HttpResponse::Ok();
+            response.insert_header((VARY, "accept-encoding"));
 
             // Add metadata headers
             response.insert_header((
@@ -112,16 +210,23 @@ pub async fn handle_get(req: HttpRequest, data: web::Data<AppState>) -> ActixRes
             ));
             response.insert_header(("gresource-folder", resource.get_folder_path()));
             response.insert_header(("gresource-size", resource.size.to_string()));
+            if let Some(hash) = &resource.content_hash {
+                response.insert_header(("gresource-content-hash", hash.as_str()));
+            }
 
-            data.logger.log_debug(&format!("Returning resource content for: {}", path));
+            debug!("Returning resource content for: {}", path);
+            if let Some(gzipped) = gzipped {
+                response.insert_header((CONTENT_ENCODING, "gzip"));
+                return Ok(response.body(gzipped));
+            }
             Ok(response.body(resource.content.unwrap_or_default()))
         }
         Ok(None) => {
-            data.logger.log_debug(&format!("Resource not found, attempting to list as folder: {}", path));
+            debug!("Resource not found, attempting to list as folder: {}", path);
             // Try to list as folder
-            match data.db.list_folder_resources(&path) {
+            match data.store.list_folder_resources(&path) {
                 Ok(folder_info) => {
-                    data.logger.log_info(&format!("Folder found: {}, contains {} resources", path, folder_info.resources.len()));
+                    info!("Folder found: {}, contains {} resources", path, folder_info.resources.len());
                     let mut response = HttpResponse::Ok();
 
                     // Add folder metadata headers
@@ -136,80 +241,89 @@ pub async fn handle_get(req: HttpRequest, data: web::Data<AppState>) -> ActixRes
 
                     // Create response body with resource paths
                     let body = folder_info.resources.join("\n");
-                    data.logger.log_debug(&format!("Returning folder listing for: {} with {} resources", path, folder_info.resources.len()));
+                    debug!("Returning folder listing for: {} with {} resources", path, folder_info.resources.len());
                     Ok(response.body(body))
                 }
+                Err(StoreError::NotFound(_)) => {
+                    info!("Neither resource nor folder found: {}", path);
+                    Err(ApiError::NotFound("Resource not found".to_string()).into())
+                }
                 Err(e) => {
-                    data.logger.log_info(&format!("Neither resource nor folder found: {}, error: {}", path, e));
-                    Ok(HttpResponse::NotFound().body("Resource not found"))
+                    error!("Database error while listing folder {}: {}", path, e);
+                    Err(ApiError::from(e).into())
                 }
             }
         }
         Err(e) => {
-            data.logger.log_error(&format!("Database error while getting resource {}: {}", path, e));
-            Ok(HttpResponse::InternalServerError().body(format!("Database error: {}", e)))
+            error!("Database error while getting resource {}: {}", path, e);
+            Err(ApiError::from(e).into())
         }
     }
 }
 
 pub async fn handle_patch(
     req: HttpRequest,
-    body: String,
+    body: web::Bytes,
     data: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
-    let path = normalize_path(req.path());
+    let path = normalize_request_path(req.path(), &data.limits);
+    let event = AuditEvent::from_request(&req, "PATCH", &path);
+    check_content_encoding(&req, &data, &event)?;
 
-    data.logger.log_info(&"");
-    data.logger.log_info(&format!("PATCH request received for path: {}", path));
-    data.logger.log_debug(&format!("Request body length: {} bytes", body.len()));
+    let body = match String::from_utf8(body.to_vec()) {
+        Ok(body) => body,
+        Err(_) => {
+            warn!("Rejected non UTF-8 body for {}", path);
+            data.record_write(&event.failed());
+            return Err(ApiError::from(ValidationError::NotUtf8).into());
+        }
+    };
 
-    data.logger.log_debug(&format!("Validating path: {}", path));
-    if let Err(e) = validate_path(&path) {
-        data.logger.log_warn(&format!("Path validation failed for {}: {}", path, e));
-        data.logger.log_write_operation("PATCH", &path, false);
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid path: {}", e)));
-    }
-    data.logger.log_debug("Path validation successful");
+    info!("PATCH request received for path: {}", path);
+    debug!("Request body length: {} bytes", body.len());
 
-    data.logger.log_debug(&format!("Validating content (length: {})", body.len()));
-    if let Err(e) = validate_content(&body) {
-        data.logger.log_warn(&format!("Content validation failed for {}: {}", path, e));
-        data.logger.log_write_operation("PATCH", &path, false);
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid content: {}", e)));
+    debug!("Validating path: {}", path);
+    if let Err(e) = validate_path_with_limits(&path, &data.limits) {
+        warn!("Path validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
     }
-    data.logger.log_debug("Content validation successful");
+    debug!("Path validation successful");
 
-    // Check if resource exists
-    data.logger.log_debug(&format!("Checking if resource exists: {}", path));
-    match data.db.resource_exists(&path) {
-        Ok(false) => {
-            data.logger.log_info(&format!("Resource not found for PATCH: {}", path));
-            data.logger.log_write_operation("PATCH", &path, false);
-            return Ok(HttpResponse::NotFound().body("Resource not found"));
-        }
-        Ok(true) => {
-            data.logger.log_debug("Resource exists, proceeding with update");
-        }
-        Err(e) => {
-            data.logger.log_error(&format!("Database error while checking resource existence for {}: {}", path, e));
-            data.logger.log_write_operation("PATCH", &path, false);
-            return Ok(HttpResponse::InternalServerError().body(format!("Database error: {}", e)));
-        }
+    debug!("Validating content (length: {})", body.len());
+    if let Err(e) = validate_content_with_limits(&body, &data.limits) {
+        warn!("Content validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
     }
+    debug!("Content validation successful");
 
-    // Update the resource
-    data.logger.log_debug(&format!("Updating resource: {}", path));
-    match data.db.update_resource(&path, &body) {
-        Ok(_) => {
-            data.logger.log_info(&format!("Resource updated successfully: {}", path));
-            data.logger.log_write_operation("PATCH", &path, true);
+    let content_type = request_content_type(&req);
+    enforce_policy(&data, &event, |policy| policy.check_write(&path, &body, Some(content_type)))?;
+
+    // Update the resource, the store refuses atomically if it does not exist
+    // or a quota would be exceeded
+    debug!("Updating resource: {}", path);
+    match data.store.update_resource(&path, &body, &data.quotas) {
+        Ok(previous) => {
+            info!("Resource updated successfully: {}", path);
+            data.record_write(&event.succeeded(Some(previous.size), Some(&body)));
             Ok(HttpResponse::NoContent().finish()) // 204 No Content with no body
         }
+        Err(StoreError::NotFound(_)) => {
+            info!("Resource not found for PATCH: {}", path);
+            data.record_write(&event.failed());
+            Err(ApiError::NotFound("Resource not found".to_string()).into())
+        }
+        Err(e @ StoreError::QuotaExceeded(_)) => {
+            warn!("Rejected PATCH {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
         Err(e) => {
-            data.logger.log_error(&format!("Failed to update resource {}: {}", path, e));
-            data.logger.log_write_operation("PATCH", &path, false);
-            Ok(HttpResponse::InternalServerError()
-                .body(format!("Failed to update resource: {}", e)))
+            error!("Failed to update resource {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
         }
     }
 }
@@ -218,63 +332,387 @@ pub async fn handle_delete(
     req: HttpRequest,
     data: web::Data<AppState>,
 ) -> ActixResult<HttpResponse> {
-    let path = normalize_path(req.path());
+    let path = normalize_request_path(req.path(), &data.limits);
+    let event = AuditEvent::from_request(&req, "DELETE", &path);
 
-    data.logger.log_info(&"");
-    data.logger.log_info(&format!("DELETE request received for path: {}", path));
+    info!("DELETE request received for path: {}", path);
 
-    data.logger.log_debug(&format!("Validating path: {}", path));
-    if let Err(e) = validate_path(&path) {
-        data.logger.log_warn(&format!("Path validation failed for {}: {}", path, e));
-        data.logger.log_write_operation("DELETE", &path, true);
-        return Ok(HttpResponse::BadRequest().body(format!("Invalid path: {}", e)));
+    debug!("Validating path: {}", path);
+    if let Err(e) = validate_path_with_limits(&path, &data.limits) {
+        warn!("Path validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
     }
-    data.logger.log_debug("Path validation successful");
-
-    // Check if it's a resource
-    data.logger.log_debug(&format!("Checking if path is a resource: {}", path));
-    match data.db.get_resource(&path) {
-        Ok(Some(_)) => {
-            data.logger.log_info(&format!("Found resource to delete: {}", path));
-            // It's a resource, delete it
-            match data.db.delete_resource(&path) {
-                Ok(_) => {
-                    data.logger.log_info(&format!("Resource deleted successfully: {}", path));
-                    data.logger.log_write_operation("DELETE", &path, true);
-                    Ok(HttpResponse::Ok().finish()) // 200 OK with no body
-                }
-                Err(e) => {
-                    data.logger.log_error(&format!("Failed to delete resource {}: {}", path, e));
-                    data.logger.log_write_operation("DELETE", &path, false);
-                    Ok(HttpResponse::InternalServerError()
-                        .body(format!("Failed to delete resource: {}", e)))
-                }
-            }
+    debug!("Path validation successful");
+
+    enforce_policy(&data, &event, |policy| policy.check_delete())?;
+
+    // Try it as a resource first, the lookup and removal are one store operation
+    debug!("Deleting resource: {}", path);
+    match data.store.delete_resource(&path) {
+        Ok(resource) => {
+            info!("Resource deleted successfully: {}", path);
+            data.record_write(&event.succeeded(Some(resource.size), None));
+            Ok(HttpResponse::Ok().finish()) // 200 OK with no body
         }
-        Ok(None) => {
-            data.logger.log_debug(&format!("Path is not a resource, checking if it's an empty folder: {}", path));
+        Err(StoreError::NotFound(_)) => {
+            debug!("Path is not a resource, checking if it's an empty folder: {}", path);
             // Check if it's an empty folder
-            match data.db.folder_is_empty(&path) {
+            match data.store.folder_is_empty(&path) {
                 Ok(true) => {
-                    data.logger.log_info(&format!("Found empty folder to delete: {}", path));
+                    info!("Found empty folder to delete: {}", path);
                     // It's an empty folder, we can "delete" it (no actual deletion needed since folders are implicit)
-                    data.logger.log_write_operation("DELETE", &path, true);
+                    data.record_write(&event.succeeded(None, None));
                     Ok(HttpResponse::Ok().finish()) // 200 OK with no body
                 }
                 Ok(false) => {
-                    data.logger.log_warn(&format!("Attempt to delete non-empty folder: {}", path));
-                    Ok(HttpResponse::BadRequest().body("Cannot delete non-empty folder"))
+                    warn!("Attempt to delete non-empty folder: {}", path);
+                    data.record_write(&event.failed());
+                    Err(ApiError::FolderNotEmpty("Cannot delete non-empty folder".to_string()).into())
                 }
                 Err(e) => {
-                    data.logger.log_info(&format!("Path not found as resource or folder: {}, error: {}", path, e));
-                    Ok(HttpResponse::NotFound().body("Resource or folder not found"))
+                    error!("Database error while checking folder {}: {}", path, e);
+                    data.record_write(&event.failed());
+                    Err(ApiError::from(e).into())
                 }
             }
         }
         Err(e) => {
-            data.logger.log_error(&format!("Database error while checking resource {}: {}", path, e));
-            data.logger.log_write_operation("DELETE", &path, false);
-            Ok(HttpResponse::InternalServerError().body(format!("Database error: {}", e)))
+            error!("Failed to delete resource {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
         }
     }
 }
+
+async fn handle_archive_export(
+    path: &str,
+    format: &str,
+    data: &web::Data<AppState>,
+) -> ActixResult<HttpResponse> {
+    let Some(format) = ArchiveFormat::from_query(format) else {
+        return Err(ApiError::bad_request("unsupported_format", format!("Unsupported archive format: {}", format)).into());
+    };
+
+    info!("Exporting folder {} as {:?} archive", path, format);
+
+    // Collecting a large subtree is blocking work. Only the root exists while
+    // empty, so nothing below a non-root path means it is not a folder
+    let store = data.store.clone();
+    let folder = path.to_string();
+    let result = web::block(move || match store.folder_resources(&folder) {
+        Ok(resources) if resources.is_empty() && folder != "/" => {
+            Err(StoreError::NotFound(format!("Folder {}", folder)))
+        }
+        other => other,
+    })
+    .await?;
+
+    let resources = match result {
+        Ok(resources) => resources,
+        Err(StoreError::NotFound(_)) => {
+            info!("Folder not found for export: {}", path);
+            return Err(ApiError::NotFound("Folder not found".to_string()).into());
+        }
+        Err(e) => {
+            error!("Failed to export folder {}: {}", path, e);
+            return Err(ApiError::from(e).into());
+        }
+    };
+
+    // The archive is packed on a blocking thread and sent out as it grows. A
+    // failure part way aborts the response instead of ending it cleanly
+    let (sender, receiver) = mpsc::channel(EXPORT_CHUNKS_IN_FLIGHT);
+    let folder = path.to_string();
+    actix_web::rt::task::spawn_blocking(move || {
+        match write_archive(format, &folder, &resources, ChannelWriter(sender.clone())) {
+            Ok(()) => info!("Exported {} resources from {}", resources.len(), folder),
+            Err(e) => {
+                error!("Failed to export folder {}: {}", folder, e);
+                let _ = sender.blocking_send(Err(io::Error::other(e.to_string())));
+            }
+        }
+    });
+
+    let file_name = path.rsplit('/').find(|s| !s.is_empty()).unwrap_or("root");
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "content-disposition",
+            format!("attachment; filename=\"{}.{}\"", file_name, format.extension()),
+        ))
+        .body(ChannelBody(receiver)))
+}
+
+/// Chunks of an export waiting to be sent before the writer blocks.
+const EXPORT_CHUNKS_IN_FLIGHT: usize = 8;
+
+/// Hands everything written to it to a response body on the async side.
+struct ChannelWriter(mpsc::Sender<io::Result<web::Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .blocking_send(Ok(web::Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Response body fed by a [`ChannelWriter`].
+struct ChannelBody(mpsc::Receiver<io::Result<web::Bytes>>);
+
+impl MessageBody for ChannelBody {
+    type Error = io::Error;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<web::Bytes>>> {
+        self.get_mut().0.poll_recv(cx)
+    }
+}
+
+/// Creates `path` as a copy of the resource at `from`. The store checks the
+/// folder policy and quotas against the resource it actually copies, with the
+/// request's `Content-Type` as the media type of the copied body. The global
+/// limits are not checked again, the source met them when it was stored.
+fn handle_copy(
+    req: &HttpRequest,
+    path: &str,
+    from: &str,
+    data: &web::Data<AppState>,
+    event: &AuditEvent,
+) -> ActixResult<HttpResponse> {
+    let from = normalize_path_with_limits(from, &data.limits);
+    info!("Copy requested from {} to {}", from, path);
+
+    for path in [&from[..], path] {
+        if let Err(e) = validate_path_with_limits(path, &data.limits) {
+            warn!("Path validation failed for {}: {}", path, e);
+            data.record_write(&event.failed());
+            return Err(ApiError::from(e).into());
+        }
+    }
+
+    let content_type = request_content_type(req);
+    match data.store.copy_resource(&from, path, Some(content_type), &data.quotas) {
+        Ok(copy) => {
+            info!("Resource {} copied to {}", from, path);
+            data.record_write(&event.created(&copy));
+            Ok(HttpResponse::Created().finish())
+        }
+        Err(e) => {
+            warn!("Failed to copy {} to {}: {}", from, path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+async fn handle_folder_stats(path: &str, data: &web::Data<AppState>) -> ActixResult<HttpResponse> {
+    info!("Statistics requested for folder: {}", path);
+
+    let store = data.store.clone();
+    let folder = path.to_string();
+    let result = web::block(move || store.folder_stats(&folder)).await?;
+
+    match result {
+        Ok(Some(stats)) => {
+            debug!(
+                "Folder {} holds {} resources, {} bytes",
+                path, stats.resources, stats.bytes
+            );
+            Ok(HttpResponse::Ok().json(stats))
+        }
+        Ok(None) => {
+            info!("Folder not found for statistics: {}", path);
+            Err(ApiError::NotFound("Folder not found".to_string()).into())
+        }
+        Err(e) => {
+            error!("Failed to compute statistics for {}: {}", path, e);
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+async fn handle_archive_import(
+    path: &str,
+    format: &str,
+    body: &web::Bytes,
+    data: &web::Data<AppState>,
+    event: &AuditEvent,
+) -> ActixResult<HttpResponse> {
+    let Some(format) = ArchiveFormat::from_query(format) else {
+        data.record_write(&event.failed());
+        return Err(ApiError::bad_request("unsupported_format", format!("Unsupported archive format: {}", format)).into());
+    };
+
+    info!("Importing {:?} archive into {} ({} bytes)", format, path, body.len());
+
+    if let Err(e) = validate_path_with_limits(path, &data.limits) {
+        warn!("Path validation failed for {}: {}", path, e);
+        data.record_write(&event.failed());
+        return Err(ApiError::from(e).into());
+    }
+
+    let resources = match import_archive(format, path, body, &data.limits) {
+        Ok(resources) => resources,
+        Err(e) => {
+            warn!("Archive rejected for {}: {}", path, e);
+            data.record_write(&event.failed());
+            return Err(ApiError::bad_request("invalid_archive", format!("Invalid archive: {}", e)).into());
+        }
+    };
+
+    let policies = load_policies(data, event)?;
+    for resource in &resources {
+        let Some(policy) = governing_policy(&policies, &resource.path) else {
+            continue;
+        };
+        // Entries carry no media type of their own, so folders that restrict
+        // it take no archives
+        let content = resource.content.as_deref().unwrap_or_default();
+        let checked = if policy.content_types.is_empty() {
+            policy.check_write(&resource.path, content, None)
+        } else {
+            Err(ValidationError::ContentTypeNotAllowed(format.content_type().to_string()))
+        };
+        if let Err(e) = checked {
+            warn!("Archive rejected for {}: {}: {}", path, resource.path, e);
+            data.record_write(&event.failed());
+            return Err(ApiError::from(e).into());
+        }
+    }
+
+    // The store refuses existing paths inside its own transaction
+    let store = data.store.clone();
+    let quotas = data.quotas;
+    let written: Vec<AuditEvent> = resources
+        .iter()
+        .map(|r| event.at_path(&r.path).succeeded(None, r.content.as_deref()))
+        .collect();
+    let result = web::block(move || store.load_resources(&resources, ConflictPolicy::Fail, &quotas)).await?;
+
+    match result {
+        Ok(_) => {
+            info!("Imported {} resources into {}", written.len(), path);
+            for write in &written {
+                data.record_write(write);
+            }
+            let paths: Vec<&str> = written.iter().map(|w| w.path.as_str()).collect();
+            Ok(HttpResponse::Created().body(paths.join("\n")))
+        }
+        Err(e @ StoreError::Conflict(_)) => {
+            info!("Archive import into {} conflicts: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+        Err(e @ StoreError::QuotaExceeded(_)) => {
+            warn!("Rejected archive import into {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+        Err(e) => {
+            error!("Failed to import archive into {}: {}", path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e).into())
+        }
+    }
+}
+
+/// Whether the client takes a gzip-encoded response, per `Accept-Encoding`.
+/// An explicit `gzip` entry wins over `*`.
+fn accepts_gzip(req: &HttpRequest) -> bool {
+    let codings: Vec<(String, f32)> = req
+        .headers()
+        .get_all(ACCEPT_ENCODING)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|coding| {
+            let mut parts = coding.split(';');
+            let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            (name, quality)
+        })
+        .collect();
+
+    let quality = |name: &str| codings.iter().find(|(coding, _)| coding == name).map(|(_, q)| *q);
+    quality("gzip")
+        .or_else(|| quality("x-gzip"))
+        .or_else(|| quality("*"))
+        .is_some_and(|q| q > 0.0)
+}
+
+/// Rejects a body in an encoding actix cannot decode; it would otherwise be
+/// stored as the raw encoded bytes.
+fn check_content_encoding(
+    req: &HttpRequest,
+    data: &web::Data<AppState>,
+    event: &AuditEvent,
+) -> Result<(), ApiError> {
+    let Some(value) = req.headers().get(CONTENT_ENCODING) else {
+        return Ok(());
+    };
+
+    match value.to_str().ok().map(str::parse::<ContentEncoding>) {
+        Some(Ok(_)) => Ok(()),
+        _ => {
+            let encoding = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            warn!("Rejected {} {} with content encoding {}", event.method, event.path, encoding);
+            data.record_write(&event.failed());
+            Err(ApiError::UnsupportedEncoding(format!("Unsupported content encoding: {}", encoding)))
+        }
+    }
+}
+
+/// Media type of the request body. A request without one is treated as
+/// arbitrary bytes, as HTTP prescribes.
+fn request_content_type(req: &HttpRequest) -> &str {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+}
+
+/// Rejects a write that `check` finds the folder policy governing the
+/// event's path does not allow.
+fn enforce_policy(
+    data: &web::Data<AppState>,
+    event: &AuditEvent,
+    check: impl FnOnce(&FolderPolicy) -> Result<(), ValidationError>,
+) -> Result<(), ApiError> {
+    let policies = load_policies(data, event)?;
+    let Some(policy) = governing_policy(&policies, &event.path) else {
+        return Ok(());
+    };
+
+    match check(policy) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            warn!("Rejected {} {} by the policy for {}: {}", event.method, event.path, policy.folder, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e))
+        }
+    }
+}
+
+fn load_policies(data: &web::Data<AppState>, event: &AuditEvent) -> Result<Vec<FolderPolicy>, ApiError> {
+    match data.store.folder_policies() {
+        Ok(policies) => Ok(policies),
+        Err(e) => {
+            error!("Failed to read folder policies for {}: {}", event.path, e);
+            data.record_write(&event.failed());
+            Err(ApiError::from(e))
+        }
+    }
+}
+
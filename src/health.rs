@@ -0,0 +1,103 @@
+use crate::handlers::AppState;
+use crate::logging::app_log;
+use actix_web::{web, HttpResponse, Result as ActixResult};
+use log::warn;
+use serde::Serialize;
+use serde_json::json;
+
+/// Outcome of one readiness check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Liveness probe: answers as long as the process can serve requests.
+pub async fn handle_health() -> ActixResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+    })))
+}
+
+/// Readiness probe: 200 when every check passes, 503 with the failing
+/// checks otherwise.
+pub async fn handle_ready(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    let state = data.clone();
+    let checks = web::block(move || readiness_checks(&state)).await?;
+
+    let ready = checks.iter().all(|check| check.ok);
+    if !ready {
+        for check in checks.iter().filter(|check| !check.ok) {
+            warn!("Readiness check {} failed: {}", check.name, check.detail);
+        }
+    }
+
+    let body = json!({
+        "status": if ready { "ready" } else { "unavailable" },
+        "checks": checks,
+    });
+    if ready {
+        Ok(HttpResponse::Ok().json(body))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(body))
+    }
+}
+
+pub fn readiness_checks(data: &AppState) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    checks.push(match data.store.ping() {
+        Ok(()) => Check::new("database", true, "reachable"),
+        Err(e) => Check::new("database", false, e.to_string()),
+    });
+
+    checks.push(match data.store.schema_problems() {
+        Ok(problems) if problems.is_empty() => Check::new("schema", true, "up to date"),
+        Ok(problems) => Check::new("schema", false, problems.join("; ")),
+        Err(e) => Check::new("schema", false, e.to_string()),
+    });
+
+    let mut sinks = vec![data.audit.sink()];
+    sinks.extend(app_log().map(|sink| sink.as_ref()));
+    for sink in sinks {
+        checks.push(match sink.check_writable() {
+            Ok(()) => Check::new("log", true, sink.path().display().to_string()),
+            Err(e) => Check::new("log", false, format!("{}: {}", sink.path().display(), e)),
+        });
+    }
+
+    for dir in &data.data_dirs {
+        checks.push(match fs2::available_space(dir) {
+            Ok(free) if free >= data.min_free_disk_bytes => Check::new(
+                "disk",
+                true,
+                format!("{} bytes free in {}", free, dir.display()),
+            ),
+            Ok(free) => Check::new(
+                "disk",
+                false,
+                format!(
+                    "{} bytes free in {}, below the minimum of {}",
+                    free,
+                    dir.display(),
+                    data.min_free_disk_bytes
+                ),
+            ),
+            Err(e) => Check::new("disk", false, format!("{}: {}", dir.display(), e)),
+        });
+    }
+
+    checks
+}
@@ -1,6 +1,19 @@
+pub mod admin;
+pub mod archive;
+pub mod audit;
+pub mod cli;
 pub mod database;
+pub mod dump;
+pub mod error;
+pub mod fs_store;
 pub mod handlers;
+pub mod health;
 pub mod logging;
+pub mod memory_store;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod quota;
 pub mod settings;
+pub mod store;
 pub mod validation;
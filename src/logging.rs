@@ -1,92 +1,422 @@
-use anyhow::Result;
-use log::error;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use crate::audit::AuditEvent;
+use crate::metrics::metrics;
+use crate::models::DEFAULT_USER_ID;
+use crate::settings::Settings;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use env_filter::Filter;
+use log::kv::{self, VisitSource};
+use log::{info, Level, Log, Metadata, Record};
+use serde_json::{json, Map, Value};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-pub struct Logger {
-    log_file: Mutex<std::fs::File>,
+/// Application log, filtered by the configured level.
+pub const LOG_FILE_NAME: &str = "gresources.log";
+
+/// Audit log of every write, always on.
+pub const AUDIT_FILE_NAME: &str = "audit.log";
+
+/// Response header carrying the request's correlation ID. A valid ID sent by
+/// the client in the same header is reused so calls can be traced end to end.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Target of the per-request access lines, so they can be filtered on their own.
+pub const ACCESS_TARGET: &str = "gresources::access";
+
+/// Buffered lines are written out at least this often.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+static APP_LOG: OnceLock<Arc<LogSink>> = OnceLock::new();
+
+/// When a log file is rotated and how many rotated files are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogRotation {
+    /// Rotate once the file would grow beyond this size.
+    pub max_bytes: u64,
+    /// Also rotate on the first write of each UTC day.
+    pub daily: bool,
+    /// Rotated files to keep; older ones are deleted.
+    pub max_files: usize,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            daily: true,
+            max_files: 7,
+        }
+    }
+}
+
+/// A JSON-lines log file with size and daily rotation.
+///
+/// Every line written while handling a request carries that request's ID.
+pub struct LogSink {
+    file: Mutex<LogFile>,
+    path: PathBuf,
+    rotation: LogRotation,
 }
 
-impl Logger {
-    pub fn new() -> Result<Self> {
-        let log_dir = Self::get_log_directory()?;
-        std::fs::create_dir_all(&log_dir)?;
+struct LogFile {
+    writer: BufWriter<File>,
+    size: u64,
+    opened_on: NaiveDate,
+    last_flush: Instant,
+}
 
-        let log_file_path = log_dir.join("gresources.log");
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(log_file_path)?;
+impl LogSink {
+    pub fn open(path: impl Into<PathBuf>, rotation: LogRotation) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = LogFile::open(&path)?;
 
         Ok(Self {
-            log_file: Mutex::new(file),
+            file: Mutex::new(file),
+            path,
+            rotation,
         })
     }
 
-    pub fn log_write_operation(&self, operation: &str, path: &str, success: bool) {
-        let timestamp = chrono::Utc::now()
-            .format("%Y-%m-%d %H:%M:%S%.3f")
-            .to_string();
-        let status = if success { "SUCCESS" } else { "FAILED" };
-        let log_entry = format!("[{}] {} {} - {}\n", timestamp, operation, path, status);
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fails if the file can no longer be opened for writing,
+    /// e.g. because it was removed or its permissions changed.
+    pub fn check_writable(&self) -> Result<()> {
+        OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Appends one line holding the timestamp, `level`, the current request
+    /// ID if any, and `fields`.
+    pub fn write(&self, level: Level, fields: Map<String, Value>) {
+        let now = Utc::now();
+        let mut record = Map::new();
+        record.insert(
+            "timestamp".to_string(),
+            json!(now.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+        record.insert("level".to_string(), json!(level.as_str()));
+        if let Ok(request_id) = REQUEST_ID.try_with(|id| id.clone()) {
+            record.insert("request_id".to_string(), json!(request_id));
+        }
+        record.extend(fields);
+
+        let mut line = Value::Object(record).to_string();
+        line.push('\n');
 
-        if let Ok(mut file) = self.log_file.lock() {
-            if let Err(e) = file.write_all(log_entry.as_bytes()) {
-                error!("Failed to write to log file: {}", e);
-            } else {
-                let _ = file.flush();
+        // Failures cannot go through `log` here, that could end up in this very sink
+        let Ok(mut file) = self.file.lock() else {
+            metrics().log_write_failures.inc();
+            return;
+        };
+        if let Err(e) = self.write_line(&mut file, &line, now, level) {
+            metrics().log_write_failures.inc();
+            eprintln!("Failed to write to {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Writes out buffered lines.
+    pub fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Err(e) = file.flush() {
+                metrics().log_write_failures.inc();
+                eprintln!("Failed to flush {}: {}", self.path.display(), e);
             }
         }
+    }
+
+    fn write_line(&self, file: &mut LogFile, line: &str, now: DateTime<Utc>, level: Level) -> Result<()> {
+        let too_big = file.size > 0 && file.size + line.len() as u64 > self.rotation.max_bytes;
+        let new_day = self.rotation.daily && now.date_naive() != file.opened_on;
+        if too_big || new_day {
+            file.flush()?;
+            *file = self.rotate(now)?;
+        }
 
-        // Also log to console (simple version)
-        println!("{} {} - {}", operation, path, status);
+        file.writer.write_all(line.as_bytes())?;
+        file.size += line.len() as u64;
+
+        // Problems are written out right away, everything else in batches
+        if level <= Level::Warn || file.last_flush.elapsed() >= FLUSH_INTERVAL {
+            file.flush()?;
+        }
+
+        Ok(())
     }
 
-    // Enhanced method for detailed logging to file
-    pub fn log_detailed(&self, level: &str, message: &str) {
-        let timestamp = chrono::Utc::now()
-            .format("%Y-%m-%d %H:%M:%S%.3f")
-            .to_string();
-        let log_entry = format!("[{}] {} {}\n", timestamp, level, message);
+    /// Renames the current file with a timestamp suffix, deletes rotated
+    /// files beyond the retention limit and opens a fresh file.
+    fn rotate(&self, now: DateTime<Utc>) -> Result<LogFile> {
+        let dir = self.path.parent().unwrap_or_else(|| Path::new("."));
+        let file_name = self
+            .path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("Invalid log file name: {}", self.path.display()))?;
 
-        if let Ok(mut file) = self.log_file.lock() {
-            if let Err(e) = file.write_all(log_entry.as_bytes()) {
-                error!("Failed to write to log file: {}", e);
-            } else {
-                let _ = file.flush();
-            }
+        let stamp = now.format("%Y%m%dT%H%M%S%.3f");
+        let mut rotated = dir.join(format!("{}.{}", file_name, stamp));
+        let mut counter = 1;
+        while rotated.exists() {
+            rotated = dir.join(format!("{}.{}-{}", file_name, stamp, counter));
+            counter += 1;
+        }
+        fs::rename(&self.path, &rotated)?;
+
+        let prefix = format!("{}.", file_name);
+        let mut old_files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&prefix))
+            })
+            .collect();
+        // Suffixes are timestamps, so name order is age order
+        old_files.sort();
+        let excess = old_files.len().saturating_sub(self.rotation.max_files);
+        for path in &old_files[..excess] {
+            fs::remove_file(path)?;
+        }
+
+        LogFile::open(&self.path)
+    }
+}
+
+impl LogFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        let modified: DateTime<Utc> = metadata.modified()?.into();
+
+        Ok(Self {
+            size: metadata.len(),
+            // An existing file started on the day it was last written
+            opened_on: modified.date_naive(),
+            writer: BufWriter::new(file),
+            last_flush: Instant::now(),
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.last_flush = Instant::now();
+        self.writer.flush()
+    }
+}
+
+/// `log` backend writing every record that passes the filter to the
+/// application log; errors are echoed to stderr as well.
+pub struct AppLogger {
+    filter: Filter,
+    sink: Arc<LogSink>,
+}
+
+impl AppLogger {
+    pub fn new(filter: Filter, sink: Arc<LogSink>) -> Self {
+        Self { filter, sink }
+    }
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+
+        let mut fields = Map::new();
+        fields.insert("target".to_string(), json!(record.target()));
+        fields.insert("message".to_string(), json!(record.args().to_string()));
+        let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+        self.sink.write(record.level(), fields);
+
+        if record.level() == Level::Error {
+            eprintln!("ERROR {}: {}", record.target(), record.args());
         }
     }
 
-    pub fn log_info(&self, message: &str) {
-        self.log_detailed("INFO", message);
+    fn flush(&self) {
+        self.sink.flush();
     }
+}
+
+/// Copies a record's key-value pairs into the JSON line.
+struct JsonFields<'a>(&'a mut Map<String, Value>);
 
-    pub fn log_debug(&self, message: &str) {
-        self.log_detailed("DEBUG", message);
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(n) = value.to_i64() {
+            json!(n)
+        } else if let Some(n) = value.to_u64() {
+            json!(n)
+        } else if let Some(n) = value.to_f64() {
+            json!(n)
+        } else if let Some(b) = value.to_bool() {
+            json!(b)
+        } else {
+            json!(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
     }
+}
+
+/// Parses a filter in `RUST_LOG` syntax, e.g. `info,gresources::database=debug`.
+pub fn parse_filter(spec: &str) -> Result<Filter> {
+    let mut builder = env_filter::Builder::new();
+    builder
+        .try_parse(spec)
+        .map_err(|e| anyhow!("Invalid log filter {:?}: {}", spec, e))?;
+    Ok(builder.build())
+}
+
+/// Installs the application log as the `log` backend. `RUST_LOG`, when set,
+/// takes precedence over the `log_level` setting.
+pub fn init(settings: &Settings) -> Result<()> {
+    let spec = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|spec| !spec.trim().is_empty())
+        .unwrap_or_else(|| settings.log_level.clone());
+    let filter = parse_filter(&spec)?;
+
+    let sink = Arc::new(LogSink::open(
+        settings.log_directory()?.join(LOG_FILE_NAME),
+        settings.log_rotation(),
+    )?);
+
+    log::set_max_level(filter.filter());
+    log::set_boxed_logger(Box::new(AppLogger::new(filter, sink.clone())))
+        .map_err(|e| anyhow!("Failed to install logger: {}", e))?;
+    let _ = APP_LOG.set(sink);
 
-    pub fn log_warn(&self, message: &str) {
-        self.log_detailed("WARN", message);
+    Ok(())
+}
+
+/// The application log installed by `init`, if any.
+pub fn app_log() -> Option<&'static Arc<LogSink>> {
+    APP_LOG.get()
+}
+
+/// Record of every write operation, independent of the configured log level.
+pub struct AuditLog {
+    sink: LogSink,
+}
+
+impl AuditLog {
+    pub fn new(log_dir: &Path) -> Result<Self> {
+        Self::with_rotation(log_dir, LogRotation::default())
     }
 
-    pub fn log_error(&self, message: &str) {
-        self.log_detailed("ERROR", message);
+    pub fn with_rotation(log_dir: &Path, rotation: LogRotation) -> Result<Self> {
+        Ok(Self {
+            sink: LogSink::open(log_dir.join(AUDIT_FILE_NAME), rotation)?,
+        })
     }
 
-    #[cfg(target_os = "windows")]
-    fn get_log_directory() -> Result<PathBuf> {
-        let local_app_data = std::env::var("LOCALAPPDATA")
-            .map_err(|_| anyhow::anyhow!("LOCALAPPDATA environment variable not found"))?;
-        Ok(PathBuf::from(local_app_data).join("gresources"))
+    pub fn sink(&self) -> &LogSink {
+        &self.sink
     }
 
-    #[cfg(not(target_os = "windows"))]
-    fn get_log_directory() -> Result<PathBuf> {
-        let home = std::env::var("HOME")
-            .map_err(|_| anyhow::anyhow!("HOME environment variable not found"))?;
-        Ok(PathBuf::from(home).join(".local/share/gresources"))
+    pub fn log_write_operation(&self, event: &AuditEvent) {
+        let mut fields = Map::new();
+        fields.insert("event".to_string(), json!("write"));
+        fields.insert("operation".to_string(), json!(event.method));
+        fields.insert("path".to_string(), json!(event.path));
+        fields.insert("outcome".to_string(), json!(event.outcome()));
+        fields.insert("user".to_string(), json!(event.user_id));
+        fields.insert("client_ip".to_string(), json!(event.client_ip));
+        fields.insert("old_size".to_string(), json!(event.old_size));
+        fields.insert("new_size".to_string(), json!(event.new_size));
+        fields.insert("content_hash".to_string(), json!(event.content_hash));
+        self.sink.write(Level::Info, fields);
     }
 }
+
+#[cfg(target_os = "windows")]
+pub fn default_log_directory() -> Result<PathBuf> {
+    let local_app_data = std::env::var("LOCALAPPDATA")
+        .map_err(|_| anyhow::anyhow!("LOCALAPPDATA environment variable not found"))?;
+    Ok(PathBuf::from(local_app_data).join("gresources"))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn default_log_directory() -> Result<PathBuf> {
+    let home = std::env::var("HOME")
+        .map_err(|_| anyhow::anyhow!("HOME environment variable not found"))?;
+    Ok(PathBuf::from(home).join(".local/share/gresources"))
+}
+
+/// Middleware assigning every request a correlation ID, returning it in the
+/// `x-request-id` header and writing one access log line per request.
+pub async fn log_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let start = Instant::now();
+
+    let result = REQUEST_ID.scope(request_id.clone(), next.call(req)).await;
+
+    let status = match &result {
+        Ok(response) => response.status().as_u16(),
+        Err(e) => e.as_response_error().status_code().as_u16(),
+    };
+    REQUEST_ID.sync_scope(request_id.clone(), || {
+        info!(
+            target: ACCESS_TARGET,
+            event = "request",
+            method = method.as_str(),
+            path = path.as_str(),
+            status = status,
+            latency_ms = start.elapsed().as_secs_f64() * 1000.0,
+            user = DEFAULT_USER_ID;
+            "request"
+        )
+    });
+
+    let mut response = result?;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+
+    Ok(response)
+}
+
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
@@ -1,51 +1,102 @@
-mod database;
-mod handlers;
-mod logging;
-mod models;
-mod settings;
-mod validation;
-
-use actix_web::{middleware::Logger as ActixLogger, web, App, HttpServer};
+use actix_web::{middleware::from_fn, web, App, HttpServer};
 use anyhow::Result;
+use clap::Parser;
 use std::sync::Arc;
 
-use crate::database::Database;
-use crate::handlers::{handle_delete, handle_get, handle_patch, handle_post, AppState};
-use crate::logging::Logger;
-use crate::settings::Settings;
+use gresources::admin::{
+    handle_create_snapshot, handle_dump, handle_list_policies, handle_list_snapshots, handle_load,
+    handle_remove_policy, handle_restore_snapshot, handle_set_policy, handle_usage,
+};
+use gresources::audit::handle_audit;
+use gresources::cli::{run_command, Cli, Command};
+use gresources::error::{payload_config, payload_problems, query_config};
+use gresources::handlers::{handle_delete, handle_get, handle_patch, handle_post, AppState};
+use gresources::health::{handle_health, handle_ready};
+use gresources::logging::{self, log_requests, AuditLog, FLUSH_INTERVAL};
+use gresources::metrics::{handle_metrics, track_requests};
+use gresources::settings::Settings;
+use gresources::store::open_store;
 
 #[actix_web::main]
 async fn main() -> Result<()> {
-    // Initialize logging with minimal console output (only errors)
-    std::env::set_var("RUST_LOG", "error");
-    env_logger::init();
+    let cli = Cli::parse();
+    let mut settings = cli.global.load_settings()?;
 
-    println!("Starting GResources application...");
+    match cli.command {
+        None => serve(settings).await,
+        Some(Command::Serve { host, port }) => {
+            if let Some(host) = host {
+                settings.host = host;
+            }
+            if let Some(port) = port {
+                settings.port = port;
+            }
+            serve(settings).await
+        }
+        Some(command) => run_command(command, &settings),
+    }
+}
 
-    // Load settings
-    let settings = Settings::load()?;
+async fn serve(settings: Settings) -> Result<()> {
+    println!("Starting GResources application...");
     println!("Settings loaded: host={}, port={}", 
                settings.host, settings.port);
 
-    // Initialize database
-    let db = Arc::new(Database::new(&settings)?);
-    println!("Database initialized");
+    // Initialize logging first so storage startup messages are recorded
+    logging::init(&settings)?;
+
+    // Initialize storage backend
+    let store = open_store(&settings)?;
+    println!("Storage initialized ({:?} backend)", settings.storage_backend);
 
-    // Initialize logger
-    let logger = Arc::new(Logger::new()?);
-    println!("Application logger initialized");
+    let audit = Arc::new(AuditLog::with_rotation(
+        &settings.log_directory()?,
+        settings.log_rotation(),
+    )?);
+    println!("Application and audit logs initialized");
+
+    // Buffered log lines are written out periodically
+    let flush_audit = audit.clone();
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            log::logger().flush();
+            flush_audit.sink().flush();
+        }
+    });
 
     // Create app state
-    let app_state = web::Data::new(AppState { db, logger });
+    let app_state = web::Data::new(AppState::new(store, audit.clone(), &settings));
 
     let bind_address = format!("{}:{}", settings.host, settings.port);
     println!("Starting GResources server on {}", bind_address);
 
     // Start HTTP server
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
-            .wrap(ActixLogger::default())
+            .app_data(payload_config())
+            .app_data(query_config())
+            .wrap(from_fn(payload_problems))
+            .wrap(from_fn(track_requests))
+            .wrap(from_fn(log_requests))
+            .route("/_metrics", web::get().to(handle_metrics))
+            .route("/_health", web::get().to(handle_health))
+            .route("/_ready", web::get().to(handle_ready))
+            .route("/_audit", web::get().to(handle_audit))
+            .route("/_admin/dump", web::get().to(handle_dump))
+            .route("/_admin/load", web::post().to(handle_load))
+            .route("/_admin/usage", web::get().to(handle_usage))
+            .route("/_admin/policies", web::get().to(handle_list_policies))
+            .route("/_admin/policies/{folder:.*}", web::put().to(handle_set_policy))
+            .route("/_admin/policies/{folder:.*}", web::delete().to(handle_remove_policy))
+            .route("/_admin/snapshots", web::get().to(handle_list_snapshots))
+            .route("/_admin/snapshots/{name}", web::post().to(handle_create_snapshot))
+            .route(
+                "/_admin/snapshots/{name}/restore",
+                web::post().to(handle_restore_snapshot),
+            )
             .route("/{path:.*}", web::post().to(handle_post))
             .route("/{path:.*}", web::get().to(handle_get))
             .route("/{path:.*}", web::patch().to(handle_patch))
@@ -54,10 +105,16 @@ async fn main() -> Result<()> {
             .route("/", web::post().to(handle_post))
             .route("/", web::patch().to(handle_patch))
             .route("/", web::delete().to(handle_delete))
-    })
-    .bind(bind_address)?
-    .run()
-    .await?;
+    });
+    if let Some(workers) = settings.workers {
+        server = server.workers(workers);
+    }
+
+    server.bind(bind_address)?.run().await?;
+
+    log::logger().flush();
+    audit.sink().flush();
 
     Ok(())
 }
+
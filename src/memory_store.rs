@@ -0,0 +1,242 @@
+use crate::audit::content_hash;
+use crate::models::{parent_folders, ConflictPolicy, FolderInfo, LoadReport, Resource, UsageReport};
+use crate::quota::{Quotas, UsageChange};
+use crate::store::{check_quotas, ResourceStore, StoreError, StoreResult};
+use chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Non-persistent store keeping every resource in memory.
+///
+/// Useful for unit tests and throwaway servers; everything is lost when
+/// the process exits.
+pub struct MemoryStore {
+    inner: Mutex<MemoryStoreInner>,
+}
+
+struct MemoryStoreInner {
+    resources: BTreeMap<String, Resource>,
+    next_id: i64,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(MemoryStoreInner {
+                resources: BTreeMap::new(),
+                next_id: 1,
+            }),
+        }
+    }
+
+    fn lock(&self) -> StoreResult<std::sync::MutexGuard<'_, MemoryStoreInner>> {
+        self.inner
+            .lock()
+            .map_err(|_| StoreError::LockPoisoned("memory store"))
+    }
+}
+
+impl MemoryStoreInner {
+    fn usage(&self) -> UsageReport {
+        let mut report = UsageReport::default();
+        for resource in self.resources.values() {
+            report.add(resource.user_id, &resource.path, resource.size, 1);
+        }
+
+        report
+    }
+
+    /// Refuses to create `path` if one of its parent folders is a resource or
+    /// resources already exist below it. The root is always a folder.
+    fn check_namespace(&self, path: &str) -> StoreResult<()> {
+        if path == "/" {
+            return Err(StoreError::Conflict("Folder /".to_string()));
+        }
+        if let Some(parent) = parent_folders(path).into_iter().find(|p| self.resources.contains_key(*p)) {
+            return Err(StoreError::Conflict(format!("Resource {}", parent)));
+        }
+
+        let prefix = format!("{}/", path);
+        if self
+            .resources
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(child, _)| child.starts_with(&prefix))
+        {
+            return Err(StoreError::Conflict(format!("Folder {}", path)));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceStore for MemoryStore {
+    fn create_resource(&self, resource: &Resource, quotas: &Quotas) -> StoreResult<i64> {
+        let mut inner = self.lock()?;
+
+        if inner.resources.contains_key(&resource.path) {
+            return Err(StoreError::Conflict(format!("Resource {}", resource.path)));
+        }
+        inner.check_namespace(&resource.path)?;
+        check_quotas(quotas, &[UsageChange::created(resource)], || Ok(inner.usage()))?;
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+
+        let mut stored = resource.clone();
+        stored.id = Some(id);
+        inner.resources.insert(stored.path.clone(), stored);
+
+        Ok(id)
+    }
+
+    fn get_resource(&self, path: &str) -> StoreResult<Option<Resource>> {
+        let inner = self.lock()?;
+        Ok(inner.resources.get(path).cloned())
+    }
+
+    fn update_resource(&self, path: &str, content: &str, quotas: &Quotas) -> StoreResult<Resource> {
+        let mut inner = self.lock()?;
+
+        let previous = inner
+            .resources
+            .get(path)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", path)))?;
+        let change = UsageChange {
+            user_id: previous.user_id,
+            path: path.to_string(),
+            bytes: content.len() as i64 - previous.size,
+            resources: 0,
+        };
+        check_quotas(quotas, &[change], || Ok(inner.usage()))?;
+
+        let mut resource = previous.clone();
+        resource.content = Some(content.to_string());
+        resource.content_hash = Some(content_hash(content));
+        resource.size = content.len() as i64;
+        resource.updated_at = Utc::now();
+        inner.resources.insert(path.to_string(), resource);
+
+        Ok(previous)
+    }
+
+    fn delete_resource(&self, path: &str) -> StoreResult<Resource> {
+        let mut inner = self.lock()?;
+
+        inner
+            .resources
+            .remove(path)
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", path)))
+    }
+
+    fn list_folder_resources(&self, folder_path: &str) -> StoreResult<FolderInfo> {
+        let inner = self.lock()?;
+
+        let normalized_folder = if folder_path == "/" {
+            ""
+        } else {
+            folder_path.trim_end_matches('/')
+        };
+        let prefix = format!("{}/", normalized_folder);
+
+        let resources: Vec<String> = inner
+            .resources
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        // The root folder always exists, other folders only while they hold resources
+        if !normalized_folder.is_empty() && resources.is_empty() {
+            return Err(StoreError::NotFound(format!("Folder {}", folder_path)));
+        }
+
+        Ok(FolderInfo {
+            path: folder_path.to_string(),
+            created_at: Utc::now(),
+            resources,
+        })
+    }
+
+    fn resource_exists(&self, path: &str) -> StoreResult<bool> {
+        let inner = self.lock()?;
+        Ok(inner.resources.contains_key(path))
+    }
+
+    fn folder_is_empty(&self, folder_path: &str) -> StoreResult<bool> {
+        let inner = self.lock()?;
+
+        let prefix = format!("{}/", folder_path.trim_end_matches('/'));
+        let is_empty = !inner
+            .resources
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(path, _)| path.starts_with(&prefix));
+
+        Ok(is_empty)
+    }
+
+    fn usage(&self) -> StoreResult<UsageReport> {
+        Ok(self.lock()?.usage())
+    }
+
+    fn load_resources(
+        &self,
+        resources: &[Resource],
+        policy: ConflictPolicy,
+        quotas: &Quotas,
+    ) -> StoreResult<LoadReport> {
+        let mut inner = self.lock()?;
+
+        if policy == ConflictPolicy::Fail {
+            if let Some(resource) = resources.iter().find(|r| inner.resources.contains_key(&r.path)) {
+                return Err(StoreError::Conflict(format!("Resource {}", resource.path)));
+            }
+        }
+
+        // Writes go to a copy so a namespace conflict halfway leaves nothing behind
+        let mut loaded = MemoryStoreInner {
+            resources: inner.resources.clone(),
+            next_id: inner.next_id,
+        };
+        let mut changes = Vec::new();
+        let mut report = LoadReport::default();
+        for resource in resources {
+            let existing_id = loaded.resources.get(&resource.path).and_then(|r| r.id);
+            let id = match (existing_id, policy) {
+                (None, _) => {
+                    loaded.check_namespace(&resource.path)?;
+                    report.created += 1;
+                    let id = loaded.next_id;
+                    loaded.next_id += 1;
+                    id
+                }
+                (Some(id), ConflictPolicy::Overwrite) => {
+                    changes.push(UsageChange::deleted(&loaded.resources[&resource.path]));
+                    report.overwritten += 1;
+                    id
+                }
+                (Some(_), _) => {
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+
+            let mut stored = resource.clone();
+            stored.id = Some(id);
+            changes.push(UsageChange::created(&stored));
+            loaded.resources.insert(stored.path.clone(), stored);
+        }
+        check_quotas(quotas, &changes, || Ok(inner.usage()))?;
+        *inner = loaded;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::error::ApiError;
+use crate::handlers::AppState;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse, Result as ActixResult};
+use log::error;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// Collectors exported on `/_metrics`.
+///
+/// They live in a process-wide registry so the store and the logger can
+/// record into them without carrying a handle around.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub db_lock_wait: Histogram,
+    pub log_write_failures: IntCounter,
+    pub resources_stored: IntGauge,
+    pub bytes_stored: IntGauge,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("gresources".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled, by method and status"),
+            &["method", "status"],
+        )
+        .expect("valid metric");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency, by method and status",
+            ),
+            &["method", "status"],
+        )
+        .expect("valid metric");
+        let db_lock_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_lock_wait_seconds",
+                "Time spent waiting for the SQLite connection lock",
+            )
+            .buckets(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+        )
+        .expect("valid metric");
+        let log_write_failures = IntCounter::new(
+            "log_write_failures_total",
+            "Lines that could not be written to the application log",
+        )
+        .expect("valid metric");
+        let resources_stored =
+            IntGauge::new("resources_stored", "Resources currently stored").expect("valid metric");
+        let bytes_stored =
+            IntGauge::new("bytes_stored", "Total size of the stored resources in bytes")
+                .expect("valid metric");
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(db_lock_wait.clone()),
+            Box::new(log_write_failures.clone()),
+            Box::new(resources_stored.clone()),
+            Box::new(bytes_stored.clone()),
+        ] {
+            registry.register(collector).expect("metric registered once");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_lock_wait,
+            log_write_failures,
+            resources_stored,
+            bytes_stored,
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        self.http_requests.with_label_values(&[method, &status]).inc();
+        self.http_request_duration
+            .with_label_values(&[method, &status])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Middleware counting and timing every request.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let start = Instant::now();
+
+    let result = next.call(req).await;
+    let status = match &result {
+        Ok(response) => response.status().as_u16(),
+        Err(e) => e.as_response_error().status_code().as_u16(),
+    };
+    metrics().observe_request(&method, status, start.elapsed());
+
+    result
+}
+
+pub async fn handle_metrics(data: web::Data<AppState>) -> ActixResult<HttpResponse> {
+    // Storage totals are sampled at scrape time rather than tracked on every write
+    let store = data.store.clone();
+    match web::block(move || store.usage()).await? {
+        Ok(usage) => {
+            let (resources, bytes) = usage
+                .users
+                .values()
+                .fold((0, 0), |(r, b), u| (r + u.resources, b + u.bytes));
+            metrics().resources_stored.set(resources);
+            metrics().bytes_stored.set(bytes);
+        }
+        Err(e) => error!("Failed to read usage for metrics: {}", e),
+    }
+
+    match metrics().render() {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4")
+            .body(body)),
+        Err(e) => Err(ApiError::from(e).into()),
+    }
+}
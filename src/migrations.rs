@@ -0,0 +1,228 @@
+use crate::audit::content_hash;
+use anyhow::{anyhow, Result};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+
+/// A schema change compiled into the binary.
+///
+/// The version of the last applied migration is stored in `PRAGMA user_version`,
+/// so migrations must be appended with increasing versions and never edited once
+/// released.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../db/migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "usage",
+        sql: include_str!("../db/migrations/0002_usage.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "audit",
+        sql: include_str!("../db/migrations/0003_audit.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "folder_policies",
+        sql: include_str!("../db/migrations/0004_folder_policies.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "policy_schemas",
+        sql: include_str!("../db/migrations/0005_policy_schemas.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "content_store",
+        sql: include_str!("../db/migrations/0006_content_store.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "compressed_contents",
+        sql: include_str!("../db/migrations/0007_compressed_contents.sql"),
+    },
+];
+
+/// Version of the newest migration this binary knows about.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Returns the migrations not yet applied to `conn`.
+///
+/// Databases created before migrations were tracked have `user_version = 0`
+/// but already contain the initial schema; they are treated as version 1.
+pub fn pending_migrations(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let mut version = current_version(conn)?;
+
+    if version == 0 && resources_table_exists(conn)? {
+        version = 1;
+    }
+
+    if version > latest_version() {
+        return Err(anyhow!(
+            "Database schema version {} is newer than this binary supports (latest known version is {}); upgrade gresources",
+            version,
+            latest_version()
+        ));
+    }
+
+    Ok(MIGRATIONS.iter().filter(|m| m.version > version).collect())
+}
+
+/// Applies every pending migration in order, each in its own transaction.
+///
+/// With `dry_run` nothing is changed and the migrations that would run are
+/// returned. `initial_schema` replaces the SQL of the first migration, for
+/// deployments that still ship their own schema file.
+pub fn run_migrations(
+    conn: &mut Connection,
+    initial_schema: Option<&str>,
+    dry_run: bool,
+) -> Result<Vec<&'static Migration>> {
+    let pending = pending_migrations(conn)?;
+    if dry_run {
+        return Ok(pending);
+    }
+
+    register_functions(conn)?;
+
+    // Record the baseline for legacy databases before applying anything newer
+    if current_version(conn)? == 0 && resources_table_exists(conn)? {
+        conn.pragma_update(None, "user_version", 1)?;
+    }
+
+    for migration in &pending {
+        let sql = match (migration.version, initial_schema) {
+            (1, Some(schema)) => schema,
+            _ => migration.sql,
+        };
+
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql).map_err(|e| {
+            anyhow!(
+                "Migration {} ({}) failed: {}",
+                migration.version,
+                migration.name,
+                e
+            )
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(pending)
+}
+
+/// SQL functions the migrations rely on: `sha256(text)` returns the same
+/// hex digest the server computes for stored content.
+fn register_functions(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "sha256",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(ctx.get::<Option<String>>(0)?.map(|text| content_hash(&text))),
+    )?;
+
+    Ok(())
+}
+
+fn resources_table_exists(conn: &Connection) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type='table' AND name='resources'",
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+/// Compares the live schema against the one the embedded migrations produce
+/// and describes every difference in tables, columns and indexes.
+///
+/// An empty result means the database matches what the code expects.
+pub fn schema_drift(conn: &Connection) -> Result<Vec<String>> {
+    let mut expected = Connection::open_in_memory()?;
+    run_migrations(&mut expected, None, false)?;
+
+    let expected_tables = table_names(&expected)?;
+    let live_tables = table_names(conn)?;
+    let mut drift = Vec::new();
+
+    for table in &expected_tables {
+        if !live_tables.contains(table) {
+            drift.push(format!("missing table {}", table));
+            continue;
+        }
+
+        let expected_columns = columns(&expected, table)?;
+        let live_columns = columns(conn, table)?;
+        for (name, column_type) in &expected_columns {
+            match live_columns.iter().find(|(live_name, _)| live_name == name) {
+                None => drift.push(format!("table {}: missing column {}", table, name)),
+                Some((_, live_type)) if !live_type.eq_ignore_ascii_case(column_type) => {
+                    drift.push(format!(
+                        "table {}: column {} has type {} (expected {})",
+                        table, name, live_type, column_type
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, _) in &live_columns {
+            if !expected_columns.iter().any(|(expected_name, _)| expected_name == name) {
+                drift.push(format!("table {}: unexpected column {}", table, name));
+            }
+        }
+    }
+
+    let live_indexes = index_names(conn)?;
+    for index in index_names(&expected)? {
+        if !live_indexes.contains(&index) {
+            drift.push(format!("missing index {}", index));
+        }
+    }
+
+    Ok(drift)
+}
+
+fn table_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+fn index_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type='index' AND sql IS NOT NULL ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT name, type FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    Ok(columns)
+}
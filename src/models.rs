@@ -1,5 +1,10 @@
+use crate::audit::content_hash;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Owner of every resource until users are introduced.
+pub const DEFAULT_USER_ID: i64 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Resource {
@@ -7,6 +12,8 @@ pub struct Resource {
     pub user_id: i64,
     pub path: String,
     pub content: Option<String>,
+    /// SHA-256 of `content`, hex encoded.
+    pub content_hash: Option<String>,
     pub size: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -19,8 +26,9 @@ impl Resource {
 
         Self {
             id: None,
-            user_id: 1, // Default user_id as per requirements
+            user_id: DEFAULT_USER_ID,
             path,
+            content_hash: Some(content_hash(&content)),
             content: Some(content),
             size,
             created_at: now,
@@ -47,3 +55,158 @@ pub struct FolderInfo {
     pub created_at: DateTime<Utc>,
     pub resources: Vec<String>,
 }
+
+/// Number of resources listed in `FolderStats::largest`.
+pub const LARGEST_RESOURCES: usize = 10;
+
+/// Aggregate figures for a folder and everything below it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FolderStats {
+    pub path: String,
+    pub resources: i64,
+    pub bytes: i64,
+    /// Number of folders below this one, at any depth.
+    pub folders: i64,
+    /// The biggest resources, largest first.
+    pub largest: Vec<ResourceSize>,
+    pub oldest_updated_at: Option<DateTime<Utc>>,
+    pub newest_updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResourceSize {
+    pub path: String,
+    pub size: i64,
+}
+
+impl FolderStats {
+    /// Computes the statistics of `folder_path` from every resource below it.
+    pub fn from_resources(folder_path: &str, resources: &[Resource]) -> Self {
+        let folder = folder_path.trim_end_matches('/');
+
+        let mut folders = BTreeSet::new();
+        for resource in resources {
+            let mut dir = resource.path.as_str();
+            while let Some(end) = dir.rfind('/') {
+                dir = &dir[..end];
+                if dir.len() <= folder.len() {
+                    break;
+                }
+                folders.insert(dir);
+            }
+        }
+
+        let mut largest: Vec<ResourceSize> = resources
+            .iter()
+            .map(|r| ResourceSize {
+                path: r.path.clone(),
+                size: r.size,
+            })
+            .collect();
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        largest.truncate(LARGEST_RESOURCES);
+
+        Self {
+            path: folder_path.to_string(),
+            resources: resources.len() as i64,
+            bytes: resources.iter().map(|r| r.size).sum(),
+            folders: folders.len() as i64,
+            largest,
+            oldest_updated_at: resources.iter().map(|r| r.updated_at).min(),
+            newest_updated_at: resources.iter().map(|r| r.updated_at).max(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What to do when loaded data hits a path that already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing resource and move on.
+    Skip,
+    /// Replace the existing resource with the loaded one.
+    Overwrite,
+    /// Abort the whole load without writing anything.
+    #[default]
+    Fail,
+}
+
+impl ConflictPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "skip" => Some(Self::Skip),
+            "overwrite" => Some(Self::Overwrite),
+            "fail" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LoadReport {
+    pub created: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+}
+
+/// Bytes and resource count charged to a user or a top-level folder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub bytes: i64,
+    pub resources: i64,
+}
+
+/// Current usage of every user and top-level folder holding resources.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UsageReport {
+    pub users: BTreeMap<i64, Usage>,
+    pub folders: BTreeMap<String, Usage>,
+}
+
+impl UsageReport {
+    /// Charges `bytes` and `resources` (either may be negative) for a
+    /// resource at `path` owned by `user_id`.
+    pub fn add(&mut self, user_id: i64, path: &str, bytes: i64, resources: i64) {
+        for usage in [
+            self.users.entry(user_id).or_default(),
+            self.folders.entry(top_level_folder(path)).or_default(),
+        ] {
+            usage.bytes += bytes;
+            usage.resources += resources;
+        }
+    }
+
+    pub fn user(&self, user_id: i64) -> Usage {
+        self.users.get(&user_id).copied().unwrap_or_default()
+    }
+
+    pub fn folder(&self, folder: &str) -> Usage {
+        self.folders.get(folder).copied().unwrap_or_default()
+    }
+}
+
+/// Returns the top-level folder a path is charged to: `/a` for `/a/b/c`,
+/// and `/` for resources directly under the root.
+pub fn top_level_folder(path: &str) -> String {
+    let trimmed = path.trim_start_matches('/');
+    match trimmed.find('/') {
+        Some(end) => format!("/{}", &trimmed[..end]),
+        None => "/".to_string(),
+    }
+}
+
+/// Returns the folders enclosing a path, outermost first: `/a` and `/a/b`
+/// for `/a/b/c`. The root is not included.
+pub fn parent_folders(path: &str) -> Vec<&str> {
+    path.match_indices('/')
+        .map(|(end, _)| &path[..end])
+        .filter(|folder| !folder.is_empty())
+        .collect()
+}
@@ -0,0 +1,110 @@
+use crate::models::{top_level_folder, Resource, UsageReport};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Storage quotas per user and per top-level folder; `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Quotas {
+    pub user_max_bytes: Option<u64>,
+    pub user_max_resources: Option<u64>,
+    pub folder_max_bytes: Option<u64>,
+    pub folder_max_resources: Option<u64>,
+}
+
+/// A pending write, as the change in bytes and resource count it causes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageChange {
+    pub user_id: i64,
+    pub path: String,
+    pub bytes: i64,
+    pub resources: i64,
+}
+
+impl UsageChange {
+    /// The change caused by storing `resource` at a free path.
+    pub fn created(resource: &Resource) -> Self {
+        Self {
+            user_id: resource.user_id,
+            path: resource.path.clone(),
+            bytes: resource.size,
+            resources: 1,
+        }
+    }
+
+    /// The change caused by removing `resource`.
+    pub fn deleted(resource: &Resource) -> Self {
+        Self {
+            user_id: resource.user_id,
+            path: resource.path.clone(),
+            bytes: -resource.size,
+            resources: -1,
+        }
+    }
+}
+
+impl Quotas {
+    /// No quota at all, for operator tools and tests.
+    pub const UNLIMITED: Quotas = Quotas {
+        user_max_bytes: None,
+        user_max_resources: None,
+        folder_max_bytes: None,
+        folder_max_resources: None,
+    };
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::UNLIMITED
+    }
+
+    /// Returns why applying `changes` on top of `usage` would exceed a quota.
+    ///
+    /// Only growth is checked, so a user or folder already over its quota
+    /// (e.g. after the quota was lowered) can still shrink.
+    pub fn check(&self, usage: &UsageReport, changes: &[UsageChange]) -> Option<String> {
+        let mut users: BTreeMap<i64, (i64, i64)> = BTreeMap::new();
+        let mut folders: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+        for change in changes {
+            for delta in [
+                users.entry(change.user_id).or_default(),
+                folders.entry(top_level_folder(&change.path)).or_default(),
+            ] {
+                delta.0 += change.bytes;
+                delta.1 += change.resources;
+            }
+        }
+
+        for (user_id, (bytes, resources)) in users {
+            let current = usage.user(user_id);
+            let subject = format!("user {}", user_id);
+            if let Some(message) = exceeded(&subject, "bytes", current.bytes, bytes, self.user_max_bytes)
+                .or_else(|| exceeded(&subject, "resources", current.resources, resources, self.user_max_resources))
+            {
+                return Some(message);
+            }
+        }
+
+        for (folder, (bytes, resources)) in folders {
+            let current = usage.folder(&folder);
+            let subject = format!("folder {}", folder);
+            if let Some(message) = exceeded(&subject, "bytes", current.bytes, bytes, self.folder_max_bytes)
+                .or_else(|| exceeded(&subject, "resources", current.resources, resources, self.folder_max_resources))
+            {
+                return Some(message);
+            }
+        }
+
+        None
+    }
+}
+
+fn exceeded(subject: &str, unit: &str, current: i64, delta: i64, max: Option<u64>) -> Option<String> {
+    let max = max?;
+    let total = current + delta;
+    if delta > 0 && total > max as i64 {
+        Some(format!(
+            "Quota exceeded for {}: {} {} would exceed the limit of {}",
+            subject, total, unit, max
+        ))
+    } else {
+        None
+    }
+}
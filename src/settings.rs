@@ -1,19 +1,323 @@
-use anyhow::Result;
+use crate::logging::{default_log_directory, parse_filter, LogRotation};
+use crate::quota::Quotas;
+use crate::validation::ValidationLimits;
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings file read from the working directory when no path is given.
+pub const DEFAULT_SETTINGS_FILE: &str = "Settings.toml";
+
+/// Prefix of the environment variables overriding individual settings,
+/// e.g. `GRESOURCES_PORT=9000`.
+pub const ENV_PREFIX: &str = "GRESOURCES_";
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub db_file_path: String,
-    pub db_schema_path: String,
+    /// Optional schema file replacing the embedded initial migration.
+    pub db_schema_path: Option<String>,
     pub host: String,
     pub port: u16,
+    pub storage_backend: StorageBackend,
+    /// Directory tree served by the `filesystem` backend.
+    pub storage_root: Option<String>,
+    /// Where named database snapshots are kept, defaults to `snapshots/` next to the database.
+    pub snapshot_dir: Option<String>,
+    pub max_resource_name_length: usize,
+    pub max_resource_size: usize,
+    pub max_folder_depth: usize,
+    /// Treat paths differing only in case as the same resource. Paths are
+    /// stored in lower case, so enable this before storing anything.
+    pub case_insensitive_paths: bool,
+    /// Directory for the application log, defaults to the platform's local data directory.
+    pub log_dir: Option<String>,
+    /// Level filter in `RUST_LOG` syntax, e.g. `info,gresources::database=debug`.
+    /// `RUST_LOG` itself takes precedence when set.
+    pub log_level: String,
+    /// Size in bytes at which the log files are rotated.
+    pub log_max_bytes: u64,
+    /// Rotate the log file every day as well.
+    pub log_rotate_daily: bool,
+    /// Number of rotated log files to keep.
+    pub log_max_files: usize,
+    /// HTTP worker threads, defaults to the number of CPU cores.
+    pub workers: Option<usize>,
+    /// Quotas on total bytes and resource count, unlimited when unset.
+    pub quota_user_max_bytes: Option<u64>,
+    pub quota_user_max_resources: Option<u64>,
+    pub quota_folder_max_bytes: Option<u64>,
+    pub quota_folder_max_resources: Option<u64>,
+    /// Free space below which `/_ready` reports the server as not ready.
+    pub min_free_disk_bytes: u64,
+    /// Bodies of at least this many bytes are stored gzip-compressed by the
+    /// SQLite backend; 0 disables compression.
+    pub compression_min_bytes: usize,
+}
+
+/// Every key accepted in the settings file and as a `GRESOURCES_*` variable.
+const KNOWN_KEYS: &[&str] = &[
+    "db_file_path",
+    "db_schema_path",
+    "host",
+    "port",
+    "storage_backend",
+    "storage_root",
+    "snapshot_dir",
+    "max_resource_name_length",
+    "max_resource_size",
+    "max_folder_depth",
+    "case_insensitive_paths",
+    "log_dir",
+    "log_level",
+    "log_max_bytes",
+    "log_rotate_daily",
+    "log_max_files",
+    "workers",
+    "quota_user_max_bytes",
+    "quota_user_max_resources",
+    "quota_folder_max_bytes",
+    "quota_folder_max_resources",
+    "min_free_disk_bytes",
+    "compression_min_bytes",
+];
+
+/// Which `ResourceStore` implementation the server runs on.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Sqlite,
+    Memory,
+    Filesystem,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let limits = ValidationLimits::default();
+        let rotation = LogRotation::default();
+
+        Self {
+            db_file_path: "db/database.sqlite".to_string(),
+            db_schema_path: None,
+            host: "127.0.0.1".to_string(),
+            port: 8002,
+            storage_backend: StorageBackend::default(),
+            storage_root: None,
+            snapshot_dir: None,
+            max_resource_name_length: limits.max_resource_name_length,
+            max_resource_size: limits.max_resource_size,
+            max_folder_depth: limits.max_folder_depth,
+            case_insensitive_paths: limits.case_insensitive_paths,
+            log_dir: None,
+            log_level: "info".to_string(),
+            log_max_bytes: rotation.max_bytes,
+            log_rotate_daily: rotation.daily,
+            log_max_files: rotation.max_files,
+            workers: None,
+            quota_user_max_bytes: None,
+            quota_user_max_resources: None,
+            quota_folder_max_bytes: None,
+            quota_folder_max_resources: None,
+            min_free_disk_bytes: 100 * 1024 * 1024,
+            compression_min_bytes: 1024,
+        }
+    }
 }
 
 impl Settings {
+    /// Loads `Settings.toml` from the working directory if it exists, then
+    /// applies environment overrides on top of the defaults.
     pub fn load() -> Result<Self> {
-        let contents = fs::read_to_string("Settings.toml")?;
-        let settings: Settings = toml::from_str(&contents)?;
+        let path = Path::new(DEFAULT_SETTINGS_FILE);
+        if path.exists() {
+            Self::load_from(path)
+        } else {
+            Self::from_layers(toml::Table::new(), std::env::vars())
+        }
+    }
+
+    /// Like `load`, but the given settings file must exist.
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read settings file {}: {}", path.display(), e))?;
+        let table: toml::Table = toml::from_str(&contents)
+            .map_err(|e| anyhow!("Failed to parse settings file {}: {}", path.display(), e))?;
+
+        Self::from_layers(table, std::env::vars())
+    }
+
+    /// Merges file values with `GRESOURCES_*` variables from `env` (which win)
+    /// over the defaults, and validates the result.
+    ///
+    /// Every problem is collected so the error lists all bad keys at once.
+    pub fn from_layers(
+        mut table: toml::Table,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self> {
+        let mut errors = Vec::new();
+        for (name, value) in env {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                let key = key.to_ascii_lowercase();
+                if KNOWN_KEYS.contains(&key.as_str()) {
+                    let value = env_value(&key, &value);
+                    table.insert(key, value);
+                } else {
+                    errors.push(format!("{}: unknown setting", name));
+                }
+            }
+        }
+
+        for (key, value) in &table {
+            if !KNOWN_KEYS.contains(&key.as_str()) {
+                errors.push(format!("{}: unknown setting", key));
+                continue;
+            }
+
+            // Deserialize each key on its own to report every invalid value
+            if let Err(e) = parse_single(key, value.clone()) {
+                errors.push(format!("{}: {}", key, e.message()));
+            }
+        }
+
+        let settings = if errors.is_empty() {
+            toml::Value::Table(table)
+                .try_into::<Settings>()
+                .map_err(|e| anyhow!("Invalid settings: {}", e))?
+        } else {
+            Settings::default()
+        };
+        errors.extend(settings.validate());
+
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid settings:\n  {}", errors.join("\n  ")));
+        }
+
         Ok(settings)
     }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.db_file_path.trim().is_empty() {
+            errors.push("db_file_path: must not be empty".to_string());
+        }
+        if self.host.trim().is_empty() {
+            errors.push("host: must not be empty".to_string());
+        }
+        if self.storage_backend == StorageBackend::Filesystem && self.storage_root.is_none() {
+            errors.push("storage_root: required when storage_backend is \"filesystem\"".to_string());
+        }
+        for (key, value) in [
+            ("max_resource_name_length", self.max_resource_name_length),
+            ("max_resource_size", self.max_resource_size),
+            ("max_folder_depth", self.max_folder_depth),
+        ] {
+            if value == 0 {
+                errors.push(format!("{}: must be greater than 0", key));
+            }
+        }
+        if let Err(e) = parse_filter(&self.log_level) {
+            errors.push(format!("log_level: {}", e));
+        }
+        if self.log_max_bytes == 0 {
+            errors.push("log_max_bytes: must be greater than 0".to_string());
+        }
+        if self.workers == Some(0) {
+            errors.push("workers: must be greater than 0".to_string());
+        }
+        for (key, value) in [
+            ("quota_user_max_bytes", self.quota_user_max_bytes),
+            ("quota_user_max_resources", self.quota_user_max_resources),
+            ("quota_folder_max_bytes", self.quota_folder_max_bytes),
+            ("quota_folder_max_resources", self.quota_folder_max_resources),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{}: must be greater than 0, leave it unset for no limit", key));
+            }
+        }
+
+        errors
+    }
+
+    pub fn validation_limits(&self) -> ValidationLimits {
+        ValidationLimits {
+            max_resource_name_length: self.max_resource_name_length,
+            max_resource_size: self.max_resource_size,
+            max_folder_depth: self.max_folder_depth,
+            case_insensitive_paths: self.case_insensitive_paths,
+        }
+    }
+
+    pub fn quotas(&self) -> Quotas {
+        Quotas {
+            user_max_bytes: self.quota_user_max_bytes,
+            user_max_resources: self.quota_user_max_resources,
+            folder_max_bytes: self.quota_folder_max_bytes,
+            folder_max_resources: self.quota_folder_max_resources,
+        }
+    }
+
+    pub fn log_rotation(&self) -> LogRotation {
+        LogRotation {
+            max_bytes: self.log_max_bytes,
+            daily: self.log_rotate_daily,
+            max_files: self.log_max_files,
+        }
+    }
+
+    pub fn log_directory(&self) -> Result<PathBuf> {
+        match &self.log_dir {
+            Some(dir) => Ok(PathBuf::from(dir)),
+            None => default_log_directory(),
+        }
+    }
+
+    /// Directory holding the stored data, `None` for the in-memory backend.
+    pub fn data_directory(&self) -> Option<PathBuf> {
+        match self.storage_backend {
+            StorageBackend::Sqlite => Some(
+                Path::new(&self.db_file_path)
+                    .parent()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf(),
+            ),
+            StorageBackend::Memory => None,
+            StorageBackend::Filesystem => self.storage_root.as_ref().map(PathBuf::from),
+        }
+    }
+
+    pub fn snapshot_directory(&self) -> PathBuf {
+        match &self.snapshot_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => Path::new(&self.db_file_path)
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join("snapshots"),
+        }
+    }
+}
+
+fn parse_single(key: &str, value: toml::Value) -> Result<Settings, toml::de::Error> {
+    let mut single = toml::Table::new();
+    single.insert(key.to_string(), value);
+    toml::Value::Table(single).try_into()
+}
+
+/// Interprets an environment value as a TOML value (number, boolean) when the
+/// setting accepts it that way, and as a plain string otherwise.
+fn env_value(key: &str, raw: &str) -> toml::Value {
+    let string = toml::Value::String(raw.to_string());
+
+    match toml::from_str::<toml::Table>(&format!("value = {}", raw)) {
+        Ok(mut table) => match table.remove("value") {
+            Some(typed) if parse_single(key, typed.clone()).is_ok() => typed,
+            _ => string,
+        },
+        Err(_) => string,
+    }
 }
@@ -0,0 +1,321 @@
+use crate::audit::{AuditEntry, AuditEvent, AuditFilter};
+use crate::database::Database;
+use crate::fs_store::FsStore;
+use crate::memory_store::MemoryStore;
+use crate::models::{
+    ConflictPolicy, FolderInfo, FolderStats, LoadReport, Resource, SnapshotInfo, UsageReport,
+};
+use crate::quota::{Quotas, UsageChange};
+use crate::settings::{Settings, StorageBackend};
+use crate::validation::{governing_policy, FolderPolicy, ValidationError};
+use anyhow::anyhow;
+use rusqlite::ErrorCode;
+use std::sync::Arc;
+
+pub type StoreResult<T> = std::result::Result<T, StoreError>;
+
+/// Failures reported by a `ResourceStore`, so callers can tell a missing
+/// path from a broken backend without parsing messages.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// Names what is missing, e.g. "Resource /docs/readme".
+    #[error("{0} not found")]
+    NotFound(String),
+    /// Names what is in the way, e.g. "Resource /docs/readme".
+    #[error("{0} already exists")]
+    Conflict(String),
+    #[error("Folder {0} is not empty")]
+    NotEmpty(String),
+    /// A thread panicked while holding the named lock.
+    #[error("The {0} lock is poisoned")]
+    LockPoisoned(&'static str),
+    /// Stored data that cannot be read back, e.g. an unparseable timestamp.
+    #[error("Corrupt data: {0}")]
+    Corrupt(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Invalid(#[from] ValidationError),
+    /// Says which quota the write would exceed and by how much.
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// Names the unsupported feature, e.g. "snapshots".
+    #[error("This storage backend does not support {0}")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Sqlite(rusqlite::Error),
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => Self::Corrupt(e.to_string()),
+            _ => Self::Sqlite(e),
+        }
+    }
+}
+
+/// Operations the HTTP handlers need from a storage backend.
+///
+/// A path is either a resource or a folder holding other resources, never
+/// both: creating a resource below another resource, or at a path that
+/// already has resources below it, fails with `Conflict`.
+///
+/// Each write checks for the path and changes it atomically, so concurrent
+/// writers to one path get `Conflict` or `NotFound` rather than a backend error.
+/// Writes taking `quotas` fail with `QuotaExceeded` if they would take a user
+/// or top-level folder over them, checked against the same state they change.
+pub trait ResourceStore: Send + Sync {
+    /// Creates `resource` unless its path is taken as a resource or a folder,
+    /// or one of its parent folders is a resource. Returns the new id.
+    fn create_resource(&self, resource: &Resource, quotas: &Quotas) -> StoreResult<i64>;
+
+    fn get_resource(&self, path: &str) -> StoreResult<Option<Resource>>;
+
+    /// Like `get_resource`, but a body the backend keeps gzip-compressed is
+    /// returned as stored, in the second element, with `content` left empty.
+    /// Lets clients accepting gzip be served without decompressing.
+    fn get_resource_gzipped(&self, path: &str) -> StoreResult<Option<(Resource, Option<Vec<u8>>)>> {
+        Ok(self.get_resource(path)?.map(|resource| (resource, None)))
+    }
+
+    /// Replaces the content of an existing resource, returning it as it was
+    /// before the update.
+    fn update_resource(&self, path: &str, content: &str, quotas: &Quotas) -> StoreResult<Resource>;
+
+    /// Removes an existing resource and returns it.
+    fn delete_resource(&self, path: &str) -> StoreResult<Resource>;
+
+    /// Creates a resource at `to` with the content and owner of the resource
+    /// at `from`, under the same rules as `create_resource`. The folder policy
+    /// governing `to` is checked against the content actually copied, with
+    /// `content_type` as its media type. Returns the new resource, whose
+    /// content may be left out.
+    ///
+    /// Backends storing content by hash override this to share the stored
+    /// content instead of writing it again.
+    fn copy_resource(
+        &self,
+        from: &str,
+        to: &str,
+        content_type: Option<&str>,
+        quotas: &Quotas,
+    ) -> StoreResult<Resource> {
+        let source = self
+            .get_resource(from)?
+            .ok_or_else(|| StoreError::NotFound(format!("Resource {}", from)))?;
+        let mut copy = Resource {
+            user_id: source.user_id,
+            ..Resource::new(to.to_string(), source.content.unwrap_or_default())
+        };
+        if let Some(policy) = governing_policy(&self.folder_policies()?, to) {
+            policy.check_write(to, copy.content.as_deref().unwrap_or_default(), content_type)?;
+        }
+
+        copy.id = Some(self.create_resource(&copy, quotas)?);
+        Ok(copy)
+    }
+
+    fn list_folder_resources(&self, folder_path: &str) -> StoreResult<FolderInfo>;
+
+    fn resource_exists(&self, path: &str) -> StoreResult<bool>;
+
+    fn folder_is_empty(&self, folder_path: &str) -> StoreResult<bool>;
+
+    /// Returns every resource below `folder_path`, including nested folders.
+    fn folder_resources(&self, folder_path: &str) -> StoreResult<Vec<Resource>> {
+        let folder = self.list_folder_resources(folder_path)?;
+        let mut resources = Vec::with_capacity(folder.resources.len());
+
+        for path in &folder.resources {
+            if let Some(resource) = self.get_resource(path)? {
+                resources.push(resource);
+            }
+        }
+
+        Ok(resources)
+    }
+
+    /// Cheap round trip proving the backend can serve requests.
+    fn ping(&self) -> StoreResult<()> {
+        self.resource_exists("/").map(|_| ())
+    }
+
+    /// Differences between the stored schema and the expected one, for
+    /// backends that have a schema.
+    fn schema_problems(&self) -> StoreResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Recursive statistics for `folder_path`, or `None` if the folder does not exist.
+    fn folder_stats(&self, folder_path: &str) -> StoreResult<Option<FolderStats>> {
+        if folder_path != "/" && self.folder_is_empty(folder_path)? {
+            return Ok(None);
+        }
+
+        let resources = self.folder_resources(folder_path)?;
+        Ok(Some(FolderStats::from_resources(folder_path, &resources)))
+    }
+
+    /// Writes `resources` as a batch, keeping their timestamps and owner,
+    /// and resolves paths that already exist according to `policy`.
+    ///
+    /// With `ConflictPolicy::Fail` nothing is written if any path exists, and
+    /// nothing is written if the batch as a whole would exceed `quotas`.
+    /// Backends without transactions check every path and the quotas up front
+    /// instead.
+    fn load_resources(
+        &self,
+        resources: &[Resource],
+        policy: ConflictPolicy,
+        quotas: &Quotas,
+    ) -> StoreResult<LoadReport> {
+        let mut existing = Vec::new();
+        for resource in resources {
+            existing.push(self.get_resource(&resource.path)?);
+        }
+
+        if policy == ConflictPolicy::Fail {
+            if let Some(index) = existing.iter().position(Option::is_some) {
+                return Err(StoreError::Conflict(format!("Resource {}", resources[index].path)));
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (resource, previous) in resources.iter().zip(&existing) {
+            match (previous, policy) {
+                (None, _) => changes.push(UsageChange::created(resource)),
+                (Some(previous), ConflictPolicy::Overwrite) => {
+                    changes.push(UsageChange::deleted(previous));
+                    changes.push(UsageChange::created(resource));
+                }
+                (Some(_), _) => {}
+            }
+        }
+        check_quotas(quotas, &changes, || self.usage())?;
+
+        let mut report = LoadReport::default();
+        for (resource, previous) in resources.iter().zip(existing) {
+            match (previous, policy) {
+                (None, _) => {
+                    self.create_resource(resource, &Quotas::UNLIMITED)?;
+                    report.created += 1;
+                }
+                (Some(_), ConflictPolicy::Overwrite) => {
+                    self.delete_resource(&resource.path)?;
+                    self.create_resource(resource, &Quotas::UNLIMITED)?;
+                    report.overwritten += 1;
+                }
+                (Some(_), _) => report.skipped += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Totals per user and per top-level folder, used for quota enforcement.
+    ///
+    /// The default walks every resource; backends tracking usage
+    /// incrementally should override it.
+    fn usage(&self) -> StoreResult<UsageReport> {
+        let mut report = UsageReport::default();
+        for resource in self.folder_resources("/")? {
+            report.add(resource.user_id, &resource.path, resource.size, 1);
+        }
+
+        Ok(report)
+    }
+
+    /// Whether the backend implements the snapshot operations below.
+    fn supports_snapshots(&self) -> bool {
+        false
+    }
+
+    /// Takes a consistent copy of the whole store under `name`.
+    fn create_snapshot(&self, _name: &str) -> StoreResult<SnapshotInfo> {
+        Err(StoreError::Unsupported("snapshots"))
+    }
+
+    fn list_snapshots(&self) -> StoreResult<Vec<SnapshotInfo>> {
+        Err(StoreError::Unsupported("snapshots"))
+    }
+
+    /// Replaces the whole store with the snapshot `name`.
+    fn restore_snapshot(&self, _name: &str) -> StoreResult<()> {
+        Err(StoreError::Unsupported("snapshots"))
+    }
+
+    /// Whether the backend keeps the hash-chained audit log below.
+    fn supports_audit(&self) -> bool {
+        false
+    }
+
+    /// Appends `event` to the audit log, linked to the latest entry.
+    fn record_audit(&self, _event: &AuditEvent) -> StoreResult<AuditEntry> {
+        Err(StoreError::Unsupported("the audit log"))
+    }
+
+    /// Entries matching `filter`, oldest first.
+    fn audit_entries(&self, _filter: &AuditFilter) -> StoreResult<Vec<AuditEntry>> {
+        Err(StoreError::Unsupported("the audit log"))
+    }
+
+    /// Walks the whole audit log and describes every break in the chain.
+    fn verify_audit(&self) -> StoreResult<Vec<String>> {
+        Err(StoreError::Unsupported("the audit log"))
+    }
+
+    /// Whether the backend stores the folder policies below.
+    fn supports_folder_policies(&self) -> bool {
+        false
+    }
+
+    /// Every stored policy, ordered by folder. Backends without policy
+    /// storage have none, so only the global limits apply to them.
+    fn folder_policies(&self) -> StoreResult<Vec<FolderPolicy>> {
+        Ok(Vec::new())
+    }
+
+    /// Stores `policy`, replacing any policy for the same folder.
+    fn set_folder_policy(&self, _policy: &FolderPolicy) -> StoreResult<()> {
+        Err(StoreError::Unsupported("folder policies"))
+    }
+
+    /// Removes the policy for `folder` and returns it.
+    fn remove_folder_policy(&self, _folder: &str) -> StoreResult<FolderPolicy> {
+        Err(StoreError::Unsupported("folder policies"))
+    }
+}
+
+/// Opens the storage backend selected by `settings.storage_backend`.
+/// Fails with `StoreError::QuotaExceeded` if `changes` would take a user or
+/// top-level folder over `quotas`. `usage` is only read when a quota is set.
+pub fn check_quotas(
+    quotas: &Quotas,
+    changes: &[UsageChange],
+    usage: impl FnOnce() -> StoreResult<UsageReport>,
+) -> StoreResult<()> {
+    if quotas.is_unlimited() {
+        return Ok(());
+    }
+
+    match quotas.check(&usage()?, changes) {
+        Some(message) => Err(StoreError::QuotaExceeded(message)),
+        None => Ok(()),
+    }
+}
+
+pub fn open_store(settings: &Settings) -> anyhow::Result<Arc<dyn ResourceStore>> {
+    let store: Arc<dyn ResourceStore> = match settings.storage_backend {
+        StorageBackend::Sqlite => Arc::new(Database::new(settings)?),
+        StorageBackend::Memory => Arc::new(MemoryStore::new()),
+        StorageBackend::Filesystem => {
+            let root = settings.storage_root.as_deref().ok_or_else(|| {
+                anyhow!("storage_root must be set when using the filesystem backend")
+            })?;
+            Arc::new(FsStore::new(root, settings.validation_limits())?)
+        }
+    };
+
+    Ok(store)
+}
@@ -1,36 +1,332 @@
-use anyhow::{anyhow, Result};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
+// Defaults for the limits configurable through `Settings`
 const MAX_RESOURCE_NAME_LENGTH: usize = 100;
 const MAX_RESOURCE_SIZE: usize = 5 * 1024 * 1024; // 5MB
 const MAX_FOLDER_DEPTH: usize = 5;
+const MAX_SNAPSHOT_NAME_LENGTH: usize = 64;
+/// Upper bound for request bodies, large enough for archive uploads.
+pub const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024; // 64MB
 
-pub fn validate_path(path: &str) -> Result<()> {
+/// Top-level names starting with this prefix are served by the server itself
+/// (`/_admin`, `/_metrics`, `/_health`, ...) and cannot hold resources.
+const RESERVED_ROOT_PREFIX: &str = "_";
+
+/// Size and shape limits applied to every stored resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationLimits {
+    pub max_resource_name_length: usize,
+    pub max_resource_size: usize,
+    pub max_folder_depth: usize,
+    /// Fold paths to lower case, so `/Docs` and `/docs` are the same resource.
+    pub case_insensitive_paths: bool,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        Self {
+            max_resource_name_length: MAX_RESOURCE_NAME_LENGTH,
+            max_resource_size: MAX_RESOURCE_SIZE,
+            max_folder_depth: MAX_FOLDER_DEPTH,
+            case_insensitive_paths: false,
+        }
+    }
+}
+
+/// Extra rules for writes below `folder`, stored by the backend and set by
+/// operators through `/_admin/policies`. They apply on top of the global
+/// limits, so a policy can only make them stricter.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderPolicy {
+    pub folder: String,
+    /// Counted from the root, like `ValidationLimits::max_folder_depth`.
+    pub max_folder_depth: Option<usize>,
+    pub max_resource_size: Option<usize>,
+    /// Regular expression every resource name must match in full.
+    pub name_pattern: Option<String>,
+    /// Media types accepted as request bodies; empty accepts any.
+    pub content_types: Vec<String>,
+    /// Refuses every write below the folder, deletes included.
+    pub read_only: bool,
+    /// JSON Schema every resource below the folder must conform to. Bodies
+    /// that are not JSON at all are rejected too.
+    pub json_schema: Option<Value>,
+}
+
+impl FolderPolicy {
+    /// Whether `path` is the policy's folder or lies below it.
+    pub fn governs(&self, path: &str) -> bool {
+        self.folder == "/"
+            || path
+                .strip_prefix(self.folder.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Checks the policy itself before it is stored.
+    pub fn validate(&self, limits: &ValidationLimits) -> Result<(), ValidationError> {
+        validate_path_with_limits(&self.folder, limits)?;
+
+        if let Some(pattern) = &self.name_pattern {
+            name_regex(pattern)?;
+        }
+
+        if let Some(content_type) = self.content_types.iter().find(|t| !media_type(t).contains('/')) {
+            return Err(ValidationError::InvalidPolicy(format!("{:?} is not a media type", content_type)));
+        }
+
+        if let Some(schema) = &self.json_schema {
+            schema_validator(schema)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks a write of `content` to `path`. `content_type` is the media type
+    /// of the request body, `None` where there is none, e.g. archive entries.
+    pub fn check_write(&self, path: &str, content: &str, content_type: Option<&str>) -> Result<(), ValidationError> {
+        self.check_metadata(path, content.len(), content_type)?;
+        self.check_content(content)
+    }
+
+    /// The checks of `check_write` that only need the size of the content.
+    pub fn check_metadata(&self, path: &str, size: usize, content_type: Option<&str>) -> Result<(), ValidationError> {
+        self.check_delete()?;
+
+        if let Some(max) = self.max_folder_depth {
+            if path.split('/').filter(|s| !s.is_empty()).count() > max {
+                return Err(ValidationError::TooDeep(max));
+            }
+        }
+
+        if let Some(max) = self.max_resource_size {
+            if size > max {
+                return Err(ValidationError::ContentTooLarge(max));
+            }
+        }
+
+        if let Some(pattern) = &self.name_pattern {
+            let name = path.rsplit('/').next().unwrap_or_default();
+            if !name_regex(pattern)?.is_match(name) {
+                return Err(ValidationError::NameNotAllowed(pattern.clone()));
+            }
+        }
+
+        if let Some(content_type) = content_type {
+            let content_type = media_type(content_type);
+            if !self.content_types.is_empty() && !self.content_types.iter().any(|t| media_type(t) == content_type) {
+                return Err(ValidationError::ContentTypeNotAllowed(content_type));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The check of `check_write` that needs the content itself: the JSON Schema.
+    pub fn check_content(&self, content: &str) -> Result<(), ValidationError> {
+        if let Some(schema) = &self.json_schema {
+            check_schema(schema, content)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks a delete of anything below the folder.
+    pub fn check_delete(&self) -> Result<(), ValidationError> {
+        if self.read_only {
+            return Err(ValidationError::ReadOnly(self.folder.clone()));
+        }
+
+        Ok(())
+    }
+}
+
+/// The policy governing `path`: the one with the longest matching folder.
+pub fn governing_policy<'a>(policies: &'a [FolderPolicy], path: &str) -> Option<&'a FolderPolicy> {
+    policies
+        .iter()
+        .filter(|policy| policy.governs(path))
+        .max_by_key(|policy| policy.folder.len())
+}
+
+fn name_regex(pattern: &str) -> Result<Regex, ValidationError> {
+    Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|e| ValidationError::InvalidPolicy(format!("Invalid name pattern: {}", e)))
+}
+
+fn schema_validator(schema: &Value) -> Result<jsonschema::Validator, ValidationError> {
+    jsonschema::validator_for(schema)
+        .map_err(|e| ValidationError::InvalidPolicy(format!("Invalid JSON Schema: {}", e)))
+}
+
+/// Reports every way `content` fails `schema`, not just the first.
+fn check_schema(schema: &Value, content: &str) -> Result<(), ValidationError> {
+    let instance: Value = match serde_json::from_str(content) {
+        Ok(instance) => instance,
+        Err(e) => {
+            return Err(ValidationError::SchemaViolations(vec![SchemaViolation {
+                pointer: String::new(),
+                message: format!("Invalid JSON: {}", e),
+            }]));
+        }
+    };
+
+    let violations: Vec<SchemaViolation> = schema_validator(schema)?
+        .iter_errors(&instance)
+        .map(|e| SchemaViolation {
+            pointer: e.instance_path.to_string(),
+            message: e.to_string(),
+        })
+        .collect();
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError::SchemaViolations(violations))
+    }
+}
+
+/// `text/plain; charset=utf-8` -> `text/plain`
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// One way a body fails its folder's JSON Schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value, empty for the document itself.
+    pub pointer: String,
+    pub message: String,
+}
+
+/// Why a path, content or name was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    #[error("Path cannot be empty")]
+    EmptyPath,
+    #[error("Path must start with '/'")]
+    RelativePath,
+    #[error("Maximum folder depth is {0}")]
+    TooDeep(usize),
+    #[error("Path /{0} is reserved")]
+    ReservedPath(String),
+    #[error("Resource name cannot exceed {0} characters")]
+    NameTooLong(usize),
+    #[error("Invalid characters in path")]
+    InvalidCharacters,
+    #[error("Path segments cannot be '.' or '..'")]
+    DotSegment,
+    /// The path differs from what `normalize_path_with_limits` makes of it.
+    #[error("Path is not in canonical form")]
+    NotCanonical,
+    #[error("Path contains reserved characters")]
+    ReservedCharacters,
+    #[error("Content size cannot exceed {0} bytes")]
+    ContentTooLarge(usize),
+    #[error("Body must be valid UTF-8 text")]
+    NotUtf8,
+    #[error("Snapshot name must be between 1 and {0} characters")]
+    SnapshotNameLength(usize),
+    #[error("Snapshot name may only contain letters, digits, '-', '_' and '.'")]
+    SnapshotNameCharacters,
+    #[error("Folder {0} is read-only")]
+    ReadOnly(String),
+    #[error("Resource names in this folder must match {0}")]
+    NameNotAllowed(String),
+    #[error("Content type {0} is not accepted in this folder")]
+    ContentTypeNotAllowed(String),
+    #[error("{0}")]
+    InvalidPolicy(String),
+    #[error("Content does not match the folder's JSON Schema ({} violations)", .0.len())]
+    SchemaViolations(Vec<SchemaViolation>),
+}
+
+impl ValidationError {
+    /// Stable identifier reported to API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EmptyPath => "empty_path",
+            Self::RelativePath => "relative_path",
+            Self::TooDeep(_) => "path_too_deep",
+            Self::ReservedPath(_) => "reserved_path",
+            Self::NameTooLong(_) => "name_too_long",
+            Self::InvalidCharacters => "invalid_characters",
+            Self::DotSegment => "dot_segment",
+            Self::NotCanonical => "non_canonical_path",
+            Self::ReservedCharacters => "reserved_characters",
+            Self::ContentTooLarge(_) => "content_too_large",
+            Self::NotUtf8 => "invalid_encoding",
+            Self::SnapshotNameLength(_) | Self::SnapshotNameCharacters => "invalid_snapshot_name",
+            Self::ReadOnly(_) => "read_only",
+            Self::NameNotAllowed(_) => "name_not_allowed",
+            Self::ContentTypeNotAllowed(_) => "content_type_not_allowed",
+            Self::InvalidPolicy(_) => "invalid_policy",
+            Self::SchemaViolations(_) => "schema_violation",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::ContentTooLarge(_)
+            | Self::NotUtf8
+            | Self::ContentTypeNotAllowed(_)
+            | Self::SchemaViolations(_) => "Invalid content",
+            Self::SnapshotNameLength(_) | Self::SnapshotNameCharacters => "Invalid snapshot name",
+            Self::ReadOnly(_) => "Read-only folder",
+            Self::InvalidPolicy(_) => "Invalid policy",
+            _ => "Invalid path",
+        }
+    }
+}
+
+/// Validates `path` against the default limits.
+pub fn validate_path(path: &str) -> Result<(), ValidationError> {
+    validate_path_with_limits(path, &ValidationLimits::default())
+}
+
+pub fn validate_path_with_limits(path: &str, limits: &ValidationLimits) -> Result<(), ValidationError> {
     if path.is_empty() {
-        return Err(anyhow!("Path cannot be empty"));
+        return Err(ValidationError::EmptyPath);
     }
 
     if !path.starts_with('/') {
-        return Err(anyhow!("Path must start with '/'"));
+        return Err(ValidationError::RelativePath);
     }
 
     // Check folder depth
     let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.iter().any(|s| *s == "." || *s == "..") {
+        return Err(ValidationError::DotSegment);
+    }
     let depth = segments.len();
-    if depth > MAX_FOLDER_DEPTH {
-        return Err(anyhow!("Maximum folder depth is {}", MAX_FOLDER_DEPTH));
+    if depth > limits.max_folder_depth {
+        return Err(ValidationError::TooDeep(limits.max_folder_depth));
+    }
+
+    if let Some(first) = segments.first() {
+        if first.starts_with(RESERVED_ROOT_PREFIX) {
+            return Err(ValidationError::ReservedPath(first.to_string()));
+        }
     }
 
     // Validate each path segment
     for segment in segments {
-        if segment.len() > MAX_RESOURCE_NAME_LENGTH {
-            return Err(anyhow!(
-                "Resource name cannot exceed {} characters",
-                MAX_RESOURCE_NAME_LENGTH
-            ));
+        if segment.len() > limits.max_resource_name_length {
+            return Err(ValidationError::NameTooLong(limits.max_resource_name_length));
         }
 
-        if segment.contains("..") || segment.contains('\0') {
-            return Err(anyhow!("Invalid characters in path"));
+        if segment.contains("..") || segment.chars().any(is_invalid_char) {
+            return Err(ValidationError::InvalidCharacters);
         }
 
         // Check for reserved characters that might cause issues
@@ -38,33 +334,97 @@ pub fn validate_path(path: &str) -> Result<()> {
             .chars()
             .any(|c| matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
         {
-            return Err(anyhow!("Path contains reserved characters"));
+            return Err(ValidationError::ReservedCharacters);
         }
     }
 
+    // Anything that would normalise differently could create a second row
+    // for the same resource
+    if normalize_path_with_limits(path, limits) != path {
+        return Err(ValidationError::NotCanonical);
+    }
+
+    Ok(())
+}
+
+/// Control characters, the replacement character left by undecodable
+/// percent-escapes, and invisible characters that make look-alike paths.
+fn is_invalid_char(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{FFFD}'
+                | '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2064}'
+                | '\u{FEFF}'
+        )
+}
+
+/// Validates `content` against the default limits.
+pub fn validate_content(content: &str) -> Result<(), ValidationError> {
+    validate_content_with_limits(content, &ValidationLimits::default())
+}
+
+pub fn validate_content_with_limits(content: &str, limits: &ValidationLimits) -> Result<(), ValidationError> {
+    if content.len() > limits.max_resource_size {
+        return Err(ValidationError::ContentTooLarge(limits.max_resource_size));
+    }
+
     Ok(())
 }
 
-pub fn validate_content(content: &str) -> Result<()> {
-    if content.len() > MAX_RESOURCE_SIZE {
-        return Err(anyhow!(
-            "Content size cannot exceed {} bytes",
-            MAX_RESOURCE_SIZE
-        ));
+pub fn validate_snapshot_name(name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() || name.len() > MAX_SNAPSHOT_NAME_LENGTH {
+        return Err(ValidationError::SnapshotNameLength(MAX_SNAPSHOT_NAME_LENGTH));
+    }
+
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        || name.starts_with('.')
+    {
+        return Err(ValidationError::SnapshotNameCharacters);
     }
 
     Ok(())
 }
 
+/// Brings a path into the form it is stored under: duplicate and trailing
+/// slashes removed and Unicode in NFC, so visually identical paths match.
 pub fn normalize_path(path: &str) -> String {
-    // Remove trailing slashes except for root
-    if path == "/" {
-        path.to_string()
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let joined = segments.join("/");
+    let path = if path.starts_with('/') {
+        format!("/{}", joined)
+    } else {
+        joined
+    };
+
+    path.nfc().collect()
+}
+
+/// Like `normalize_path`, also folding case when `limits` ask for it.
+pub fn normalize_path_with_limits(path: &str, limits: &ValidationLimits) -> String {
+    if limits.case_insensitive_paths {
+        normalize_path(&path.to_lowercase())
     } else {
-        path.trim_end_matches('/').to_string()
+        normalize_path(path)
     }
 }
 
+/// Normalises the path of a request URI, decoding percent-escapes exactly
+/// once. Each segment is decoded on its own, so an escaped `/` cannot add a
+/// separator. It becomes U+FFFD, like escapes that do not decode to UTF-8,
+/// and `validate_path` rejects it.
+pub fn normalize_request_path(raw: &str, limits: &ValidationLimits) -> String {
+    let decoded: Vec<String> = raw
+        .split('/')
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().replace('/', "\u{FFFD}"))
+        .collect();
+    normalize_path_with_limits(&decoded.join("/"), limits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -82,6 +442,11 @@ mod tests {
 
         let max_depth_path = "/a/b/c/d/e"; // 5 levels - should pass
         assert!(validate_path(max_depth_path).is_ok());
+
+        // System endpoints cannot be shadowed
+        assert!(validate_path("/_admin/x").is_err());
+        assert!(validate_path("/_anything").is_err());
+        assert!(validate_path("/a/_nested").is_ok());
     }
 
     #[test]
@@ -92,6 +457,22 @@ mod tests {
         assert!(validate_content(&large_content).is_err());
     }
 
+    #[test]
+    fn test_configured_limits() {
+        let limits = ValidationLimits {
+            max_resource_name_length: 3,
+            max_resource_size: 4,
+            max_folder_depth: 2,
+            case_insensitive_paths: false,
+        };
+
+        assert!(validate_path_with_limits("/abc/def", &limits).is_ok());
+        assert!(validate_path_with_limits("/abcd", &limits).is_err());
+        assert!(validate_path_with_limits("/a/b/c", &limits).is_err());
+        assert!(validate_content_with_limits("1234", &limits).is_ok());
+        assert!(validate_content_with_limits("12345", &limits).is_err());
+    }
+
     #[test]
     fn test_normalize_path() {
         assert_eq!(normalize_path("/path/"), "/path");
@@ -0,0 +1,73 @@
+use gresources::archive::{export_archive, import_archive, ArchiveFormat, MAX_ARCHIVE_ENTRIES};
+use gresources::models::Resource;
+use gresources::validation::{ValidationLimits, MAX_UPLOAD_SIZE};
+
+fn sample_resources() -> Vec<Resource> {
+    vec![
+        Resource::new("/fixtures/a.json".to_string(), "{\"a\":1}".to_string()),
+        Resource::new("/fixtures/sub/b.txt".to_string(), "hello".to_string()),
+    ]
+}
+
+#[test]
+fn test_archive_round_trip() {
+    for format in [ArchiveFormat::Tar, ArchiveFormat::Zip] {
+        let resources = sample_resources();
+        let archive = export_archive(format, "/fixtures", &resources).unwrap();
+
+        let imported = import_archive(format, "/copy", &archive, &ValidationLimits::default()).unwrap();
+        let paths: Vec<&str> = imported.iter().map(|r| r.path.as_str()).collect();
+        assert_eq!(paths, vec!["/copy/a.json", "/copy/sub/b.txt"]);
+        assert_eq!(imported[1].content.as_deref(), Some("hello"));
+        // Zip timestamps only have a two second resolution
+        let drift = resources[0].updated_at.timestamp() - imported[0].updated_at.timestamp();
+        assert!((0..2).contains(&drift), "timestamp drifted by {}s", drift);
+    }
+}
+
+#[test]
+fn test_archive_import_applies_validation() {
+    // Nesting the same entries deeper exceeds the maximum folder depth
+    let archive = export_archive(ArchiveFormat::Tar, "/fixtures", &sample_resources()).unwrap();
+    assert!(import_archive(ArchiveFormat::Tar, "/a/b/c/d", &archive, &ValidationLimits::default()).is_err());
+
+    assert!(import_archive(ArchiveFormat::Zip, "/copy", b"not an archive", &ValidationLimits::default()).is_err());
+}
+
+#[test]
+fn test_archive_format_from_query() {
+    assert_eq!(ArchiveFormat::from_query("tar"), Some(ArchiveFormat::Tar));
+    assert_eq!(ArchiveFormat::from_query("ZIP"), Some(ArchiveFormat::Zip));
+    assert_eq!(ArchiveFormat::from_query("rar"), None);
+}
+
+#[test]
+fn test_archive_import_honours_configured_limits() {
+    let archive = export_archive(ArchiveFormat::Zip, "/fixtures", &sample_resources()).unwrap();
+    let limits = ValidationLimits {
+        max_resource_size: 6,
+        ..ValidationLimits::default()
+    };
+    assert!(import_archive(ArchiveFormat::Zip, "/copy", &archive, &limits).is_err());
+}
+
+#[test]
+fn test_archive_import_bounds_the_whole_archive() {
+    // Too many entries, even empty ones
+    let many: Vec<Resource> = (0..=MAX_ARCHIVE_ENTRIES)
+        .map(|i| Resource::new(format!("/fixtures/{}", i), String::new()))
+        .collect();
+    let archive = export_archive(ArchiveFormat::Tar, "/fixtures", &many).unwrap();
+    let err = import_archive(ArchiveFormat::Tar, "/copy", &archive, &ValidationLimits::default()).unwrap_err();
+    assert!(err.to_string().contains("entries"), "{}", err);
+
+    // Entries within the resource size limit that add up to more than an upload
+    let limits = ValidationLimits::default();
+    let content = "x".repeat(limits.max_resource_size);
+    let large: Vec<Resource> = (0..(MAX_UPLOAD_SIZE / limits.max_resource_size + 1))
+        .map(|i| Resource::new(format!("/fixtures/{}", i), content.clone()))
+        .collect();
+    let archive = export_archive(ArchiveFormat::Tar, "/fixtures", &large).unwrap();
+    let err = import_archive(ArchiveFormat::Tar, "/copy", &archive, &limits).unwrap_err();
+    assert!(err.to_string().contains("unpacks to more than"), "{}", err);
+}
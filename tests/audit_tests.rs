@@ -0,0 +1,120 @@
+use actix_web::{test, web, App};
+use gresources::audit::{content_hash, handle_audit, AuditEvent, GENESIS_HASH};
+use gresources::database::Database;
+use gresources::handlers::{handle_delete, handle_patch, handle_post};
+use gresources::models::Resource;
+use gresources::quota::Quotas;
+use gresources::store::ResourceStore;
+use serde_json::Value;
+
+mod common;
+
+fn tamper(dir: &tempfile::TempDir, sql: &str) {
+    let conn = rusqlite::Connection::open(dir.path().join("test.sqlite")).unwrap();
+    conn.execute(sql, []).unwrap();
+}
+
+#[actix_web::test]
+async fn test_writes_are_recorded_and_queryable() {
+    let (_dir, store, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/_audit", web::get().to(handle_audit))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::patch().to(handle_patch))
+            .route("/{path:.*}", web::delete().to(handle_delete)),
+    )
+    .await;
+
+    let peer = "10.0.0.7:5000".parse().unwrap();
+    for request in [
+        test::TestRequest::post().uri("/docs/readme").peer_addr(peer).set_payload("hello"),
+        test::TestRequest::patch().uri("/docs/readme").set_payload("hello, world"),
+        test::TestRequest::delete().uri("/docs/bad*name"),
+        test::TestRequest::delete().uri("/docs/readme"),
+        test::TestRequest::post().uri("/other/file").set_payload("x"),
+    ] {
+        test::call_service(&app, request.to_request()).await;
+    }
+
+    let request = test::TestRequest::get().uri("/_audit?under=/docs").to_request();
+    let entries: Vec<Value> = test::call_and_read_body_json(&app, request).await;
+    let summary: Vec<(&str, &str)> = entries
+        .iter()
+        .map(|e| (e["method"].as_str().unwrap(), e["outcome"].as_str().unwrap()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![("POST", "success"), ("PATCH", "success"), ("DELETE", "failure"), ("DELETE", "success")]
+    );
+
+    assert_eq!(entries[0]["client_ip"], "10.0.0.7");
+    assert_eq!(entries[0]["user_id"], 1);
+    assert_eq!(entries[0]["new_size"], 5);
+    assert_eq!(entries[0]["content_hash"], content_hash("hello"));
+    assert_eq!(entries[0]["prev_hash"], GENESIS_HASH);
+    assert_eq!((entries[1]["old_size"].clone(), entries[1]["new_size"].clone()), (5.into(), 12.into()));
+    assert_eq!(entries[1]["prev_hash"], entries[0]["hash"]);
+    assert_eq!(entries[3]["old_size"], 5 + 7);
+    assert!(entries[3]["new_size"].is_null());
+
+    let request = test::TestRequest::get().uri("/_audit?user=2").to_request();
+    let entries: Vec<Value> = test::call_and_read_body_json(&app, request).await;
+    assert!(entries.is_empty());
+    let request = test::TestRequest::get().uri("/_audit?since=2999-01-01T00:00:00Z").to_request();
+    let entries: Vec<Value> = test::call_and_read_body_json(&app, request).await;
+    assert!(entries.is_empty());
+    let request = test::TestRequest::get().uri("/_audit?since=yesterday").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    assert!(store.verify_audit().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_tampering_breaks_the_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Database::new(&common::settings(&dir)).unwrap();
+    for path in ["/a", "/b", "/c", "/d"] {
+        store
+            .record_audit(&AuditEvent::new("POST", path).succeeded(None, Some(path)))
+            .unwrap();
+    }
+    assert!(store.verify_audit().unwrap().is_empty());
+
+    tamper(&dir, "UPDATE audit_log SET new_size = 999 WHERE id = 2");
+    assert_eq!(
+        store.verify_audit().unwrap(),
+        vec!["audit entry 2: contents do not match its hash"]
+    );
+
+    tamper(&dir, "DELETE FROM audit_log WHERE id = 3");
+    assert!(store
+        .verify_audit()
+        .unwrap()
+        .contains(&"audit entry 4: does not link to the entry before it".to_string()));
+    assert!(store.check_integrity().unwrap().iter().any(|p| p.starts_with("audit entry")));
+}
+
+#[actix_web::test]
+async fn test_restoring_a_snapshot_keeps_the_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Database::new(&common::settings(&dir)).unwrap();
+    store.create_snapshot("empty").unwrap();
+
+    store
+        .create_resource(&Resource::new("/a/one".to_string(), "1".to_string()), &Quotas::UNLIMITED)
+        .unwrap();
+    store
+        .record_audit(&AuditEvent::new("POST", "/a/one").succeeded(None, Some("1")))
+        .unwrap();
+    store.restore_snapshot("empty").unwrap();
+    store
+        .record_audit(&AuditEvent::new("RESTORE", "empty").succeeded(None, None))
+        .unwrap();
+
+    assert!(!store.resource_exists("/a/one").unwrap());
+    let entries = store.audit_entries(&Default::default()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(store.verify_audit().unwrap().is_empty());
+}
@@ -0,0 +1,94 @@
+use clap::Parser;
+use gresources::cli::{run_command, Cli, Command};
+use gresources::database::Database;
+use gresources::store::ResourceStore;
+
+fn run(dir: &tempfile::TempDir, args: &[&str]) -> anyhow::Result<()> {
+    let db = dir.path().join("cli.sqlite");
+    let config = dir.path().join("Settings.toml");
+    std::fs::write(&config, "db_file_path = \"unused.sqlite\"\nhost = \"127.0.0.1\"\nport = 0\n").unwrap();
+
+    let mut argv = vec!["gresources", "--config", config.to_str().unwrap(), "--db", db.to_str().unwrap()];
+    argv.extend_from_slice(args);
+    let cli = Cli::try_parse_from(argv)?;
+    let settings = cli.global.load_settings()?;
+    run_command(cli.command.unwrap(), &settings)
+}
+
+#[test]
+fn test_cli_parses_serve_overrides() {
+    let cli = Cli::try_parse_from(["gresources", "serve", "--host", "0.0.0.0", "--port", "9000"]).unwrap();
+    match cli.command {
+        Some(Command::Serve { host, port }) => {
+            assert_eq!(host.as_deref(), Some("0.0.0.0"));
+            assert_eq!(port, Some(9000));
+        }
+        other => panic!("unexpected command {:?}", other),
+    }
+
+    assert!(Cli::try_parse_from(["gresources"]).unwrap().command.is_none());
+    assert!(Cli::try_parse_from(["gresources", "load", "x", "--conflict", "bogus"]).is_err());
+}
+
+#[test]
+fn test_cli_manages_resources() {
+    let dir = tempfile::tempdir().unwrap();
+    let content = dir.path().join("content.txt");
+    std::fs::write(&content, "from a file").unwrap();
+
+    run(&dir, &["init-db"]).unwrap();
+    run(&dir, &["put", "/docs/readme", content.to_str().unwrap()]).unwrap();
+    run(&dir, &["ls", "/docs"]).unwrap();
+    run(&dir, &["cat", "/docs/readme"]).unwrap();
+    run(&dir, &["check"]).unwrap();
+    run(&dir, &["verify-audit"]).unwrap();
+    assert!(run(&dir, &["rm", "/docs"]).is_err());
+    assert!(run(&dir, &["put", "relative", content.to_str().unwrap()]).is_err());
+
+    let mut settings = gresources::settings::Settings::load_from(dir.path().join("Settings.toml")).unwrap();
+    settings.db_file_path = dir.path().join("cli.sqlite").to_string_lossy().into_owned();
+    let db = Database::new(&settings).unwrap();
+    assert_eq!(
+        db.get_resource("/docs/readme").unwrap().unwrap().content.as_deref(),
+        Some("from a file")
+    );
+
+    run(&dir, &["rm", "/docs/readme"]).unwrap();
+    assert!(run(&dir, &["cat", "/docs/readme"]).is_err());
+}
+
+#[test]
+fn test_cli_check_reports_inconsistent_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    run(&dir, &["init-db"]).unwrap();
+
+    let conn = rusqlite::Connection::open(dir.path().join("cli.sqlite")).unwrap();
+    conn.execute(
+        "INSERT INTO resources (path, size, created_at, updated_at) VALUES ('/bad', 99, 'yesterday', '2025-01-01T00:00:00Z')",
+        [],
+    )
+    .unwrap();
+
+    // Bad size, bad timestamp, and usage totals that never saw the row
+    let error = run(&dir, &["check"]).unwrap_err().to_string();
+    assert_eq!(error, "3 problem(s) found");
+}
+
+#[test]
+fn test_cli_inspection_needs_an_existing_database() {
+    let dir = tempfile::tempdir().unwrap();
+
+    for args in [&["check"][..], &["verify-audit"], &["ls"], &["cat", "/docs/readme"], &["rm", "/docs"], &["snapshots"]] {
+        let error = run(&dir, args).unwrap_err().to_string();
+        assert!(error.contains("does not exist"), "{:?}: {}", args, error);
+    }
+    assert!(!dir.path().join("cli.sqlite").exists());
+
+    // Inspecting leaves the schema alone
+    run(&dir, &["init-db"]).unwrap();
+    let conn = rusqlite::Connection::open(dir.path().join("cli.sqlite")).unwrap();
+    conn.pragma_update(None, "user_version", 0).unwrap();
+    run(&dir, &["check"]).unwrap();
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+    assert_eq!(version, 0);
+}
@@ -0,0 +1,36 @@
+//! Setup shared by the tests that drive the store and the HTTP handlers.
+//! Each test crate compiles its own copy and uses only part of it.
+#![allow(dead_code)]
+
+use actix_web::web;
+use gresources::database::Database;
+use gresources::handlers::AppState;
+use gresources::logging::AuditLog;
+use gresources::settings::Settings;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Settings that keep the database and snapshots inside `dir`.
+pub fn settings(dir: &TempDir) -> Settings {
+    Settings {
+        db_file_path: dir.path().join("test.sqlite").to_string_lossy().into_owned(),
+        snapshot_dir: Some(dir.path().join("snapshots").to_string_lossy().into_owned()),
+        ..Settings::default()
+    }
+}
+
+/// A fresh SQLite store in `dir`.
+pub fn database(dir: &TempDir) -> Database {
+    Database::new(&settings(dir)).unwrap()
+}
+
+/// A fresh database and the application state around it. The directory
+/// must be kept alive for as long as the store is used.
+pub fn setup() -> (TempDir, Arc<Database>, web::Data<AppState>) {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = settings(&dir);
+    let store = Arc::new(database(&dir));
+    let audit = Arc::new(AuditLog::new(&dir.path().join("logs")).unwrap());
+    let state = web::Data::new(AppState::new(store.clone(), audit, &settings));
+    (dir, store, state)
+}
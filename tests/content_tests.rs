@@ -0,0 +1,119 @@
+use actix_web::{test, web, App};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gresources::audit::content_hash;
+use gresources::handlers::{handle_get, handle_patch, handle_post};
+use serde_json::Value;
+use std::io::{Read, Write};
+
+mod common;
+
+#[actix_web::test]
+async fn test_content_hash_header_and_copies() {
+    let (_dir, store, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::get().to(handle_get)),
+    )
+    .await;
+
+    let request = test::TestRequest::post().uri("/fixtures/base").set_payload("payload").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    let request = test::TestRequest::post()
+        .uri("/runs/1/input?copy_from=/fixtures/base")
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    for path in ["/fixtures/base", "/runs/1/input"] {
+        let request = test::TestRequest::get().uri(path).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers().get("gresource-content-hash").unwrap(),
+            content_hash("payload").as_str()
+        );
+        assert_eq!(test::read_body(response).await, "payload");
+    }
+
+    // Copies follow the rules of a POST
+    let request = test::TestRequest::post()
+        .uri("/runs/1/input?copy_from=/fixtures/base")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let request = test::TestRequest::post()
+        .uri("/runs/2/input?copy_from=/fixtures/missing")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 404);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["detail"], "Resource /fixtures/missing not found");
+    let request = test::TestRequest::post()
+        .uri("/runs/2/input?copy_from=/fixtures/../base")
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    assert!(store.check_integrity().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_compressed_transfers() {
+    let (_dir, _, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::patch().to(handle_patch))
+            .route("/{path:.*}", web::get().to(handle_get)),
+    )
+    .await;
+
+    // Uploads may be gzip-encoded
+    let large = "a fixture line that repeats\n".repeat(100);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(large.as_bytes()).unwrap();
+    let request = test::TestRequest::post()
+        .uri("/fixtures/large")
+        .insert_header(("content-encoding", "gzip"))
+        .set_payload(encoder.finish().unwrap())
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    // Compressed bodies go out as stored to clients accepting gzip
+    let request = test::TestRequest::get()
+        .uri("/fixtures/large")
+        .insert_header(("accept-encoding", "br;q=1.0, gzip;q=0.8"))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+    assert_eq!(response.headers().get("gresource-size").unwrap(), large.len().to_string().as_str());
+    let body = test::read_body(response).await;
+    assert!(body.len() < large.len());
+    let mut decompressed = String::new();
+    GzDecoder::new(&body[..]).read_to_string(&mut decompressed).unwrap();
+    assert_eq!(decompressed, large);
+
+    for accept in [None, Some("gzip;q=0, *"), Some("identity")] {
+        let mut request = test::TestRequest::get().uri("/fixtures/large");
+        if let Some(accept) = accept {
+            request = request.insert_header(("accept-encoding", accept));
+        }
+        let response = test::call_service(&app, request.to_request()).await;
+        assert!(response.headers().get("content-encoding").is_none(), "{:?}", accept);
+        assert_eq!(test::read_body(response).await, large);
+    }
+
+    let request = test::TestRequest::patch()
+        .uri("/fixtures/large")
+        .insert_header(("content-encoding", "compress"))
+        .set_payload("whatever")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 415);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "unsupported_content_encoding");
+}
@@ -0,0 +1,38 @@
+use gresources::dump::{read_dump, write_dump};
+use gresources::models::Resource;
+use gresources::validation::ValidationLimits;
+
+#[test]
+fn test_dump_round_trip() {
+    let mut resources = vec![
+        Resource::new("/seed/b".to_string(), "line one\nline two".to_string()),
+        Resource::new("/seed/a".to_string(), "{\"key\": \"value\"}".to_string()),
+    ];
+    resources[0].user_id = 7;
+
+    let mut dump = Vec::new();
+    write_dump(&resources, &mut dump).unwrap();
+
+    // One record per line, sorted by path
+    let text = String::from_utf8(dump.clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("\"/seed/a\""));
+
+    let loaded = read_dump(&dump[..], &ValidationLimits::default()).unwrap();
+    assert_eq!(loaded[0].path, "/seed/a");
+    assert_eq!(loaded[1].content.as_deref(), Some("line one\nline two"));
+    assert_eq!(loaded[1].user_id, 7);
+    assert_eq!(loaded[1].created_at, resources[0].created_at);
+    assert!(loaded.iter().all(|r| r.id.is_none()));
+}
+
+#[test]
+fn test_dump_errors_report_line_numbers() {
+    let dump = "\n{\"path\": \"/ok\", \"content\": \"x\", \"user_id\": 1, \"size\": 1, \"created_at\": \"2025-01-01T00:00:00Z\", \"updated_at\": \"2025-01-01T00:00:00Z\"}\nnot json\n";
+    let error = read_dump(dump.as_bytes(), &ValidationLimits::default()).unwrap_err().to_string();
+    assert!(error.starts_with("Line 3"), "{}", error);
+
+    let invalid_path = "{\"path\": \"relative\", \"content\": \"x\", \"user_id\": 1, \"size\": 1, \"created_at\": \"2025-01-01T00:00:00Z\", \"updated_at\": \"2025-01-01T00:00:00Z\"}";
+    assert!(read_dump(invalid_path.as_bytes(), &ValidationLimits::default()).is_err());
+}
@@ -0,0 +1,146 @@
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use gresources::admin::handle_load;
+use gresources::audit::handle_audit;
+use gresources::dump::write_dump;
+use gresources::error::{payload_config, payload_problems, query_config, PROBLEM_CONTENT_TYPE};
+use gresources::handlers::{handle_delete, handle_get, handle_patch, handle_post, AppState};
+use gresources::logging::AuditLog;
+use gresources::memory_store::MemoryStore;
+use gresources::models::{FolderInfo, Resource};
+use gresources::quota::Quotas;
+use gresources::settings::{Settings, StorageBackend};
+use gresources::store::{ResourceStore, StoreError, StoreResult};
+use gresources::validation::MAX_UPLOAD_SIZE;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// A backend that finds nothing and fails every other call with an
+/// internal-looking message.
+struct BrokenStore;
+
+fn disk_error() -> StoreError {
+    StoreError::Io(std::io::Error::other("disk I/O error in /var/lib/secret.sqlite"))
+}
+
+impl ResourceStore for BrokenStore {
+    fn create_resource(&self, _resource: &Resource, _quotas: &Quotas) -> StoreResult<i64> {
+        Err(disk_error())
+    }
+
+    fn get_resource(&self, _path: &str) -> StoreResult<Option<Resource>> {
+        Ok(None)
+    }
+
+    fn update_resource(&self, _path: &str, _content: &str, _quotas: &Quotas) -> StoreResult<Resource> {
+        Err(disk_error())
+    }
+
+    fn delete_resource(&self, _path: &str) -> StoreResult<Resource> {
+        Err(disk_error())
+    }
+
+    fn list_folder_resources(&self, _folder_path: &str) -> StoreResult<FolderInfo> {
+        Err(disk_error())
+    }
+
+    fn resource_exists(&self, _path: &str) -> StoreResult<bool> {
+        Err(disk_error())
+    }
+
+    fn folder_is_empty(&self, _folder_path: &str) -> StoreResult<bool> {
+        Err(disk_error())
+    }
+}
+
+async fn problem(
+    store: Arc<dyn ResourceStore>,
+    request: test::TestRequest,
+) -> (u16, Value) {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = Settings {
+        storage_backend: StorageBackend::Memory,
+        ..Settings::default()
+    };
+    let audit = Arc::new(AuditLog::new(dir.path()).unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new(store, audit, &settings)))
+            .app_data(payload_config())
+            .app_data(query_config())
+            .wrap(from_fn(payload_problems))
+            .route("/_audit", web::get().to(handle_audit))
+            .route("/_admin/load", web::post().to(handle_load))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::get().to(handle_get))
+            .route("/{path:.*}", web::patch().to(handle_patch))
+            .route("/{path:.*}", web::delete().to(handle_delete)),
+    )
+    .await;
+
+    let response = test::call_service(&app, request.to_request()).await;
+    let status = response.status().as_u16();
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        PROBLEM_CONTENT_TYPE
+    );
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["status"], status);
+    assert_eq!(body["type"], format!("urn:gresources:problem:{}", body["code"].as_str().unwrap()));
+    (status, body)
+}
+
+#[actix_web::test]
+async fn test_client_errors_have_stable_codes() {
+    let memory = || Arc::new(MemoryStore::new()) as Arc<dyn ResourceStore>;
+
+    let (status, body) = problem(memory(), test::TestRequest::post().uri("/a/b/c/d/e/f").set_payload("x")).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("path_too_deep")));
+    assert_eq!(body["title"], "Invalid path");
+    assert_eq!(body["detail"], "Maximum folder depth is 5");
+
+    let (status, body) = problem(memory(), test::TestRequest::post().uri("/_x").set_payload("x")).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("reserved_path")));
+
+    let (status, body) = problem(memory(), test::TestRequest::patch().uri("/a").set_payload(vec![0xff, 0xfe])).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("invalid_encoding")));
+
+    let (status, body) = problem(memory(), test::TestRequest::get().uri("/missing")).await;
+    assert_eq!((status, body["code"].as_str()), (404, Some("not_found")));
+
+    let (status, body) = problem(memory(), test::TestRequest::get().uri("/a?format=rar")).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("unsupported_format")));
+
+    let (status, body) = problem(memory(), test::TestRequest::get().uri("/_audit?user=abc")).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("invalid_query")));
+
+    // Bodies over the upload limit are refused by actix before any handler runs
+    let oversized = test::TestRequest::post()
+        .uri("/a")
+        .set_payload("x")
+        .insert_header(("content-length", (MAX_UPLOAD_SIZE + 1).to_string()));
+    let (status, body) = problem(memory(), oversized).await;
+    assert_eq!((status, body["code"].as_str()), (400, Some("content_too_large")));
+}
+
+#[actix_web::test]
+async fn test_storage_errors_are_not_exposed() {
+    let (status, body) = problem(Arc::new(BrokenStore), test::TestRequest::get().uri("/docs/readme")).await;
+    assert_eq!((status, body["code"].as_str()), (500, Some("internal_error")));
+    assert!(!body.to_string().contains("secret"), "{}", body);
+
+    let (status, body) = problem(Arc::new(BrokenStore), test::TestRequest::post().uri("/docs/readme").set_payload("x")).await;
+    assert_eq!((status, body["code"].as_str()), (500, Some("internal_error")));
+    assert!(!body.to_string().contains("sqlite"), "{}", body);
+
+    // A failing folder check is a storage error, not a missing path
+    let (status, body) = problem(Arc::new(BrokenStore), test::TestRequest::delete().uri("/docs")).await;
+    assert_eq!((status, body["code"].as_str()), (500, Some("internal_error")));
+
+    // And a load that fails to write
+    let mut dump = Vec::new();
+    write_dump(&[Resource::new("/docs/readme".to_string(), "x".to_string())], &mut dump).unwrap();
+    let request = test::TestRequest::post().uri("/_admin/load?conflict=fail").set_payload(dump);
+    let (status, body) = problem(Arc::new(BrokenStore), request).await;
+    assert_eq!((status, body["code"].as_str()), (500, Some("internal_error")));
+}
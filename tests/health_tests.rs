@@ -0,0 +1,93 @@
+use actix_web::{test, web, App};
+use gresources::database::Database;
+use gresources::handlers::AppState;
+use gresources::health::{handle_health, handle_ready, readiness_checks};
+use gresources::logging::AuditLog;
+use gresources::memory_store::MemoryStore;
+use gresources::settings::{Settings, StorageBackend};
+use gresources::store::ResourceStore;
+use serde_json::Value;
+use std::sync::Arc;
+
+fn state(store: Arc<dyn ResourceStore>, dir: &tempfile::TempDir, settings: &Settings) -> AppState {
+    let audit = AuditLog::new(&dir.path().join("logs")).unwrap();
+    AppState::new(store, Arc::new(audit), settings)
+}
+
+#[actix_web::test]
+async fn test_health_and_ready() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = Settings {
+        storage_backend: StorageBackend::Memory,
+        min_free_disk_bytes: 1,
+        ..Settings::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state(Arc::new(MemoryStore::new()), &dir, &settings)))
+            .route("/_health", web::get().to(handle_health))
+            .route("/_ready", web::get().to(handle_ready)),
+    )
+    .await;
+
+    let response = test::call_service(&app, test::TestRequest::get().uri("/_health").to_request()).await;
+    assert_eq!(response.status(), 200);
+
+    let response = test::call_service(&app, test::TestRequest::get().uri("/_ready").to_request()).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["status"], "ready");
+    let names: Vec<&str> = body["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["database", "schema", "log", "disk"]);
+}
+
+#[actix_web::test]
+async fn test_not_ready_when_disk_is_low() {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = Settings {
+        min_free_disk_bytes: u64::MAX,
+        ..Settings::default()
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state(Arc::new(MemoryStore::new()), &dir, &settings)))
+            .route("/_ready", web::get().to(handle_ready)),
+    )
+    .await;
+
+    let response = test::call_service(&app, test::TestRequest::get().uri("/_ready").to_request()).await;
+    assert_eq!(response.status(), 503);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["status"], "unavailable");
+}
+
+#[actix_web::test]
+async fn test_readiness_reports_schema_drift_and_unwritable_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_file = dir.path().join("ready.sqlite");
+    let settings = Settings {
+        db_file_path: db_file.to_string_lossy().into_owned(),
+        min_free_disk_bytes: 1,
+        ..Settings::default()
+    };
+    let store = Arc::new(Database::new(&settings).unwrap());
+    let state = state(store, &dir, &settings);
+    assert!(readiness_checks(&state).iter().all(|c| c.ok));
+
+    let conn = rusqlite::Connection::open(&db_file).unwrap();
+    conn.execute_batch("DROP INDEX idx_path").unwrap();
+    std::fs::remove_dir_all(dir.path().join("logs")).unwrap();
+
+    let failed: Vec<String> = readiness_checks(&state)
+        .into_iter()
+        .filter(|c| !c.ok)
+        .map(|c| c.name)
+        .collect();
+    // The log directory is gone too, so its disk check fails as well
+    assert_eq!(failed, vec!["schema", "log", "disk"]);
+}
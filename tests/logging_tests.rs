@@ -0,0 +1,171 @@
+use actix_web::{middleware::from_fn, test, web, App};
+use gresources::audit::AuditEvent;
+use gresources::handlers::{handle_post, AppState};
+use gresources::logging::{
+    log_requests, parse_filter, AppLogger, AuditLog, LogRotation, LogSink, LOG_FILE_NAME,
+    REQUEST_ID_HEADER,
+};
+use gresources::memory_store::MemoryStore;
+use gresources::settings::{Settings, StorageBackend};
+use log::{Level, Log, Record};
+use serde_json::{Map, Value};
+use std::sync::{Arc, OnceLock};
+
+fn read_records(sink: &LogSink) -> Vec<Value> {
+    sink.flush();
+    std::fs::read_to_string(sink.path())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+/// The `log` backend can only be installed once per process, so the tests
+/// in this file share it.
+fn global_app_log() -> &'static Arc<LogSink> {
+    static APP_LOG: OnceLock<(tempfile::TempDir, Arc<LogSink>)> = OnceLock::new();
+    &APP_LOG
+        .get_or_init(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let sink = Arc::new(LogSink::open(dir.path().join(LOG_FILE_NAME), LogRotation::default()).unwrap());
+            let filter = parse_filter("info").unwrap();
+            log::set_max_level(filter.filter());
+            log::set_boxed_logger(Box::new(AppLogger::new(filter, sink.clone()))).unwrap();
+            (dir, sink)
+        })
+        .1
+}
+
+fn log_to(logger: &AppLogger, level: Level, target: &str, message: &str) {
+    logger.log(
+        &Record::builder()
+            .level(level)
+            .target(target)
+            .args(format_args!("{}", message))
+            .build(),
+    );
+}
+
+#[actix_web::test]
+async fn test_requests_are_logged_as_json_with_request_ids() {
+    let app_log = global_app_log();
+    let dir = tempfile::tempdir().unwrap();
+    let audit = Arc::new(AuditLog::new(dir.path()).unwrap());
+    let settings = Settings {
+        storage_backend: StorageBackend::Memory,
+        ..Settings::default()
+    };
+    let state = web::Data::new(AppState::new(Arc::new(MemoryStore::new()), audit.clone(), &settings));
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .wrap(from_fn(log_requests))
+            .route("/{path:.*}", web::post().to(handle_post)),
+    )
+    .await;
+
+    let request = test::TestRequest::post().uri("/docs/readme").set_payload("hello").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 201);
+    let request_id = response.headers().get(REQUEST_ID_HEADER).unwrap().to_str().unwrap().to_string();
+
+    // A caller-supplied ID is kept
+    let request = test::TestRequest::post()
+        .uri("/docs/readme")
+        .insert_header((REQUEST_ID_HEADER, "trace-42"))
+        .set_payload("again")
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    assert_eq!(response.headers().get(REQUEST_ID_HEADER).unwrap(), "trace-42");
+
+    let writes = read_records(audit.sink());
+    assert!(writes
+        .iter()
+        .any(|r| r["request_id"] == request_id.as_str() && r["event"] == "write" && r["outcome"] == "success"));
+
+    let records = read_records(app_log);
+    let access = records
+        .iter()
+        .find(|r| r["request_id"] == request_id.as_str() && r["event"] == "request")
+        .unwrap();
+    assert_eq!(access["target"], "gresources::access");
+    assert_eq!(access["method"], "POST");
+    assert_eq!(access["path"], "/docs/readme");
+    assert_eq!(access["status"], 201);
+    assert_eq!(access["user"], 1);
+    assert!(access["latency_ms"].as_f64().is_some());
+
+    assert!(records
+        .iter()
+        .any(|r| r["request_id"] == "trace-42" && r["event"] == "request" && r["status"] == 409));
+    assert!(records.iter().all(|r| r["message"] != ""));
+}
+
+#[actix_web::test]
+async fn test_level_and_per_module_filtering() {
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(LogSink::open(dir.path().join(LOG_FILE_NAME), LogRotation::default()).unwrap());
+    let logger = AppLogger::new(parse_filter("warn,gresources::database=debug").unwrap(), sink.clone());
+
+    log_to(&logger, Level::Info, "gresources::handlers", "dropped info");
+    log_to(&logger, Level::Warn, "gresources::handlers", "kept warning");
+    log_to(&logger, Level::Debug, "gresources::database", "kept debug");
+    log_to(&logger, Level::Trace, "gresources::database", "dropped trace");
+
+    let messages: Vec<Value> = read_records(&sink).iter().map(|r| r["message"].clone()).collect();
+    assert_eq!(messages, vec!["kept warning", "kept debug"]);
+
+    assert!(parse_filter("info,gresources=loud").is_err());
+    let mut settings = toml::Table::new();
+    settings.insert("log_level".to_string(), toml::Value::String("gresources=verbose".to_string()));
+    let error = Settings::from_layers(settings, Vec::new()).unwrap_err().to_string();
+    assert!(error.contains("log_level"), "{}", error);
+}
+
+#[actix_web::test]
+async fn test_audit_log_ignores_the_log_level() {
+    let dir = tempfile::tempdir().unwrap();
+    let sink = Arc::new(LogSink::open(dir.path().join(LOG_FILE_NAME), LogRotation::default()).unwrap());
+    let logger = AppLogger::new(parse_filter("off").unwrap(), sink.clone());
+    let audit = AuditLog::new(dir.path()).unwrap();
+
+    log_to(&logger, Level::Error, "gresources::handlers", "silenced");
+    audit.log_write_operation(&AuditEvent::new("DELETE", "/docs/readme").succeeded(Some(5), None));
+
+    assert!(read_records(&sink).is_empty());
+    let writes = read_records(audit.sink());
+    assert_eq!(writes.len(), 1);
+    assert_eq!(writes[0]["operation"], "DELETE");
+    assert_eq!(writes[0]["path"], "/docs/readme");
+    assert_eq!(writes[0]["old_size"], 5);
+}
+
+#[actix_web::test]
+async fn test_log_rotation_and_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let rotation = LogRotation {
+        max_bytes: 300,
+        daily: false,
+        max_files: 2,
+    };
+    let sink = LogSink::open(dir.path().join(LOG_FILE_NAME), rotation).unwrap();
+    for i in 0..40 {
+        let mut fields = Map::new();
+        fields.insert("message".to_string(), Value::from(format!("message number {}", i)));
+        sink.write(Level::Info, fields);
+    }
+    sink.flush();
+
+    let rotated: Vec<_> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with(&format!("{}.", LOG_FILE_NAME)))
+        .collect();
+    assert_eq!(rotated.len(), 2, "{:?}", rotated);
+    assert!(std::fs::metadata(sink.path()).unwrap().len() <= 300);
+
+    // The newest line is in the current file
+    let records = read_records(&sink);
+    assert_eq!(records.last().unwrap()["message"], "message number 39");
+}
@@ -0,0 +1,42 @@
+use actix_web::{middleware::from_fn, test, web, App};
+use gresources::handlers::{handle_get, handle_post, AppState};
+use gresources::logging::AuditLog;
+use gresources::memory_store::MemoryStore;
+use gresources::metrics::{handle_metrics, track_requests};
+use gresources::settings::Settings;
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn test_metrics_report_requests_and_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = web::Data::new(AppState::new(
+        Arc::new(MemoryStore::new()),
+        Arc::new(AuditLog::new(dir.path()).unwrap()),
+        &Settings::default(),
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .wrap(from_fn(track_requests))
+            .route("/_metrics", web::get().to(handle_metrics))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::get().to(handle_get)),
+    )
+    .await;
+
+    let request = test::TestRequest::post().uri("/docs/readme").set_payload("hello").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+    let request = test::TestRequest::get().uri("/docs/missing").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    let request = test::TestRequest::get().uri("/_metrics").to_request();
+    let body = test::call_and_read_body(&app, request).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body.contains(r#"gresources_http_requests_total{method="POST",status="201"} 1"#), "{}", body);
+    assert!(body.contains(r#"gresources_http_requests_total{method="GET",status="404"} 1"#));
+    assert!(body.contains("gresources_http_request_duration_seconds_bucket"));
+    assert!(body.contains("gresources_resources_stored 1"));
+    assert!(body.contains("gresources_bytes_stored 5"));
+    assert!(body.contains("gresources_log_write_failures_total 0"));
+}
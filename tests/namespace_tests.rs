@@ -0,0 +1,155 @@
+use actix_web::{test, web, App};
+use gresources::admin::handle_load;
+use gresources::archive::{export_archive, import_archive, ArchiveFormat};
+use gresources::dump::write_dump;
+use gresources::handlers::{handle_delete, handle_get, handle_patch, handle_post};
+use gresources::models::Resource;
+use gresources::validation::ValidationLimits;
+use serde_json::Value;
+
+mod common;
+
+fn resource(path: &str, content: &str) -> Resource {
+    Resource::new(path.to_string(), content.to_string())
+}
+
+#[actix_web::test]
+async fn test_a_path_is_either_a_resource_or_a_folder() {
+    let (_dir, store, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/_admin/load", web::post().to(handle_load))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::get().to(handle_get))
+            .route("/{path:.*}", web::patch().to(handle_patch))
+            .route("/{path:.*}", web::delete().to(handle_delete)),
+    )
+    .await;
+
+    for path in ["/file", "/docs/readme"] {
+        let request = test::TestRequest::post().uri(path).set_payload("x").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 201);
+    }
+
+    // POST below a resource, or at a folder
+    let request = test::TestRequest::post().uri("/file/child").set_payload("x").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "already_exists");
+    assert_eq!(body["detail"], "Resource /file already exists");
+
+    let request = test::TestRequest::post().uri("/docs").set_payload("x").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["detail"], "Folder /docs already exists");
+
+    // The root is a folder too, even before anything is stored in it
+    let request = test::TestRequest::post().uri("/").set_payload("x").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["detail"], "Folder / already exists");
+
+    // GET still tells the two apart
+    let request = test::TestRequest::get().uri("/docs").to_request();
+    let listing = test::call_and_read_body(&app, request).await;
+    assert_eq!(listing, "/docs/readme");
+    let request = test::TestRequest::get().uri("/").to_request();
+    let listing = test::call_and_read_body(&app, request).await;
+    assert_eq!(listing, "/docs/readme\n/file");
+    let request = test::TestRequest::get().uri("/file").to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await, "x");
+
+    // Only folders export as archives, however the archive is packed
+    let request = test::TestRequest::get().uri("/file?format=tar").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+    let request = test::TestRequest::get().uri("/docs?format=zip").to_request();
+    let archive = test::call_and_read_body(&app, request).await;
+    let exported = import_archive(ArchiveFormat::Zip, "/docs", &archive, &ValidationLimits::default()).unwrap();
+    assert_eq!(exported[0].path, "/docs/readme");
+
+    // PATCH only ever addresses resources, DELETE refuses folders with content
+    let request = test::TestRequest::patch().uri("/docs").set_payload("x").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+    let request = test::TestRequest::delete().uri("/docs").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    // Archive imports and dump loads are refused as a whole
+    let archive = export_archive(ArchiveFormat::Tar, "/", &[resource("/child", "y")]).unwrap();
+    let request = test::TestRequest::post()
+        .uri("/file?format=tar")
+        .set_payload(archive)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 409);
+    let archive = export_archive(ArchiveFormat::Tar, "/", &[resource("/new", "n"), resource("/docs/readme", "y")]).unwrap();
+    let request = test::TestRequest::post().uri("/?format=tar").set_payload(archive).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["detail"], "Resource /docs/readme already exists");
+    let request = test::TestRequest::get().uri("/new").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    let mut dump = Vec::new();
+    write_dump(&[resource("/fresh/one", "1"), resource("/docs/readme/nested", "2")], &mut dump).unwrap();
+    let request = test::TestRequest::post().uri("/_admin/load").set_payload(dump).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 409);
+    let request = test::TestRequest::get().uri("/fresh/one").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+    let mut dump = Vec::new();
+    write_dump(&[resource("/fresh/one", "1"), resource("/docs/readme", "2")], &mut dump).unwrap();
+    let request = test::TestRequest::post().uri("/_admin/load?conflict=fail").set_payload(dump).to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 409);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["detail"], "Resource /docs/readme already exists");
+    let request = test::TestRequest::get().uri("/fresh/one").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+
+    assert!(store.check_integrity().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_folder_prefixes_match_exactly() {
+    let (_dir, store, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::get().to(handle_get))
+            .route("/{path:.*}", web::delete().to(handle_delete)),
+    )
+    .await;
+
+    for uri in ["/x/%C3%A9%C3%A9/y", "/Docs/x", "/a_b/one", "/a%25b/two"] {
+        let request = test::TestRequest::post().uri(uri).set_payload("x").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 201, "{}", uri);
+    }
+
+    // `_` and `%` are not wildcards, even next to a multibyte sibling
+    for uri in ["/x/__", "/x/%25%25", "/aXb", "/a%25"] {
+        let request = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404, "{}", uri);
+    }
+    let request = test::TestRequest::get().uri("/a_b").to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await, "/a_b/one");
+
+    // Folders are case-sensitive, so a case variant is a different, free path
+    let request = test::TestRequest::get().uri("/docs").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+    // Nothing is below it, so it deletes like any empty folder
+    let request = test::TestRequest::delete().uri("/docs").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::post().uri("/docs").set_payload("x").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+    let request = test::TestRequest::get().uri("/Docs").to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await, "/Docs/x");
+
+    // The store is still usable afterwards
+    let request = test::TestRequest::get().uri("/x").to_request();
+    assert_eq!(test::call_and_read_body(&app, request).await, "/x/\u{e9}\u{e9}/y");
+    assert!(store.check_integrity().unwrap().is_empty());
+}
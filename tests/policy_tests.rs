@@ -0,0 +1,226 @@
+use actix_web::{test, web, App};
+use gresources::admin::{handle_list_policies, handle_remove_policy, handle_set_policy};
+use gresources::archive::{export_archive, ArchiveFormat};
+use gresources::handlers::{handle_delete, handle_patch, handle_post};
+use gresources::models::Resource;
+use serde_json::{json, Value};
+
+mod common;
+
+#[actix_web::test]
+async fn test_folder_policies_are_enforced_on_writes() {
+    let (_dir, _, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/_admin/policies", web::get().to(handle_list_policies))
+            .route("/_admin/policies/{folder:.*}", web::put().to(handle_set_policy))
+            .route("/_admin/policies/{folder:.*}", web::delete().to(handle_remove_policy))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::patch().to(handle_patch))
+            .route("/{path:.*}", web::delete().to(handle_delete)),
+    )
+    .await;
+
+    let request = test::TestRequest::post().uri("/config/app.json").set_payload("{}").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    let policies = [
+        ("/config", json!({"max_resource_size": 16, "name_pattern": "[a-z]+\\.json"})),
+        ("/config/strict", json!({"content_types": ["application/json"]})),
+        ("/config/frozen", json!({"read_only": true})),
+    ];
+    for (folder, policy) in policies {
+        let request = test::TestRequest::put()
+            .uri(&format!("/_admin/policies{}", folder))
+            .set_json(policy)
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(body["folder"], folder);
+    }
+
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/bad")
+        .set_json(json!({"name_pattern": "("}))
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "invalid_policy");
+
+    let post = |uri: &str, content_type: &str, body: &'static str| {
+        test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("content-type", content_type))
+            .set_payload(body)
+            .to_request()
+    };
+    let cases = [
+        (post("/config/db.json", "text/plain", "{}"), 201, None),
+        (post("/config/DB.json", "text/plain", "{}"), 400, Some("name_not_allowed")),
+        (post("/config/big.json", "text/plain", "{\"more\": \"than sixteen\"}"), 400, Some("content_too_large")),
+        (post("/config/strict/a.json", "text/plain", "{}"), 415, Some("content_type_not_allowed")),
+        (post("/config/strict/a.json", "application/json", "{}"), 201, None),
+        (post("/config/frozen/a.json", "application/json", "{}"), 403, Some("read_only")),
+        (post("/elsewhere/ANY", "text/plain", "free"), 201, None),
+        // A copy is checked like a POST of the source's content
+        (post("/config/strict/b.json?copy_from=/elsewhere/ANY", "text/plain", ""), 415, Some("content_type_not_allowed")),
+        (post("/config/strict/b.json?copy_from=/elsewhere/ANY", "application/json", ""), 201, None),
+        (post("/config/Copy.json?copy_from=/config/db.json", "text/plain", ""), 400, Some("name_not_allowed")),
+        (post("/config/frozen/b.json?copy_from=/config/db.json", "text/plain", ""), 403, Some("read_only")),
+    ];
+    for (request, status, code) in cases {
+        let uri = request.uri().to_string();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), status, "{}", uri);
+        if let Some(code) = code {
+            let body: Value = test::read_body_json(response).await;
+            assert_eq!(body["code"], code, "{}", uri);
+        }
+    }
+
+    // Updates and deletes are checked too
+    let request = test::TestRequest::patch()
+        .uri("/config/app.json")
+        .set_payload("{\"more\": \"than sixteen\"}")
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/config")
+        .set_json(json!({"read_only": true}))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::delete().uri("/config/app.json").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 403);
+
+    // Archive entries have no media type, so none go below a folder that restricts it
+    let archive = export_archive(
+        ArchiveFormat::Tar,
+        "/",
+        &[Resource::new("/c.json".to_string(), "{}".to_string())],
+    )
+    .unwrap();
+    let request = test::TestRequest::post()
+        .uri("/config/strict?format=tar")
+        .set_payload(archive)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 415);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "content_type_not_allowed");
+
+    // Only the longest matching policy applies, to every archive entry
+    let request = test::TestRequest::delete().uri("/_admin/policies/config").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/config/open")
+        .set_json(json!({"max_resource_size": 64}))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let archive = export_archive(
+        ArchiveFormat::Tar,
+        "/",
+        &[Resource::new("/Upper.json".to_string(), "{}".to_string())],
+    )
+    .unwrap();
+    let request = test::TestRequest::post()
+        .uri("/config/open?format=tar")
+        .set_payload(archive)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/config")
+        .set_json(json!({"name_pattern": "[a-z]+\\.json"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+    let archive = export_archive(
+        ArchiveFormat::Tar,
+        "/",
+        &[Resource::new("/Other.json".to_string(), "{}".to_string())],
+    )
+    .unwrap();
+    let request = test::TestRequest::post()
+        .uri("/config?format=tar")
+        .set_payload(archive)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    let request = test::TestRequest::get().uri("/_admin/policies").to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    let folders: Vec<&str> = body.as_array().unwrap().iter().map(|p| p["folder"].as_str().unwrap()).collect();
+    assert_eq!(folders, vec!["/config", "/config/frozen", "/config/open", "/config/strict"]);
+
+    let request = test::TestRequest::delete().uri("/_admin/policies/nowhere").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_json_schema_violations_are_listed() {
+    let (_dir, _, state) = common::setup();
+    let app = test::init_service(
+        App::new()
+            .app_data(state)
+            .route("/_admin/policies/{folder:.*}", web::put().to(handle_set_policy))
+            .route("/{path:.*}", web::post().to(handle_post))
+            .route("/{path:.*}", web::patch().to(handle_patch)),
+    )
+    .await;
+
+    let schema = json!({
+        "type": "object",
+        "properties": {"name": {"type": "string"}, "replicas": {"type": "integer", "minimum": 1}},
+        "required": ["name"],
+    });
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/configs")
+        .set_json(json!({"json_schema": schema}))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 200);
+
+    let request = test::TestRequest::put()
+        .uri("/_admin/policies/broken")
+        .set_json(json!({"json_schema": {"type": 5}}))
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 400);
+
+    let request = test::TestRequest::post()
+        .uri("/configs/web")
+        .set_payload(r#"{"name": "web", "replicas": 2}"#)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    let request = test::TestRequest::patch()
+        .uri("/configs/web")
+        .set_payload(r#"{"replicas": 0}"#)
+        .to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "schema_violation");
+    let pointers: Vec<&str> = body["violations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["pointer"].as_str().unwrap())
+        .collect();
+    assert_eq!(pointers.len(), 2);
+    assert!(pointers.contains(&"") && pointers.contains(&"/replicas"));
+
+    // Copies are validated against the content they share
+    let request = test::TestRequest::post()
+        .uri("/drafts/web")
+        .set_payload(r#"{"replicas": 0}"#)
+        .to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+    let request = test::TestRequest::post().uri("/configs/copy?copy_from=/drafts/web").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 422);
+    let request = test::TestRequest::post().uri("/configs/copy?copy_from=/configs/web").to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), 201);
+
+    let request = test::TestRequest::post().uri("/configs/db").set_payload("{").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["violations"].as_array().unwrap().len(), 1);
+}
@@ -0,0 +1,65 @@
+use gresources::models::{top_level_folder, UsageReport};
+use gresources::quota::{Quotas, UsageChange};
+
+fn change(path: &str, bytes: i64, resources: i64) -> UsageChange {
+    UsageChange {
+        user_id: 1,
+        path: path.to_string(),
+        bytes,
+        resources,
+    }
+}
+
+#[test]
+fn test_top_level_folder() {
+    assert_eq!(top_level_folder("/a/b/c"), "/a");
+    assert_eq!(top_level_folder("/a/b"), "/a");
+    assert_eq!(top_level_folder("/a"), "/");
+}
+
+#[test]
+fn test_unlimited_quotas_accept_everything() {
+    let quotas = Quotas::default();
+    assert!(quotas.is_unlimited());
+    assert!(quotas
+        .check(&UsageReport::default(), &[change("/a/b", i64::MAX / 2, 1)])
+        .is_none());
+}
+
+#[test]
+fn test_user_and_folder_quotas() {
+    let quotas = Quotas {
+        user_max_bytes: Some(100),
+        folder_max_resources: Some(2),
+        ..Quotas::default()
+    };
+    let mut usage = UsageReport::default();
+    usage.add(1, "/a/x", 60, 1);
+    usage.add(1, "/a/y", 10, 1);
+
+    assert!(quotas.check(&usage, &[change("/b/z", 30, 1)]).is_none());
+    let message = quotas.check(&usage, &[change("/b/z", 31, 1)]).unwrap();
+    assert!(message.contains("user 1"), "{}", message);
+
+    // Each top-level folder has its own resource budget
+    let message = quotas.check(&usage, &[change("/a/z", 1, 1)]).unwrap();
+    assert!(message.contains("folder /a"), "{}", message);
+    assert!(quotas.check(&usage, &[change("/b/z", 1, 1)]).is_none());
+
+    // A batch is checked as a whole
+    let batch = [change("/c/1", 1, 1), change("/c/2", 1, 1), change("/c/3", 1, 1)];
+    assert!(quotas.check(&usage, &batch).is_some());
+}
+
+#[test]
+fn test_shrinking_is_allowed_over_quota() {
+    let quotas = Quotas {
+        user_max_bytes: Some(10),
+        ..Quotas::default()
+    };
+    let mut usage = UsageReport::default();
+    usage.add(1, "/a/x", 50, 1);
+
+    assert!(quotas.check(&usage, &[change("/a/x", -20, 0)]).is_none());
+    assert!(quotas.check(&usage, &[change("/a/x", 1, 0)]).is_some());
+}
@@ -0,0 +1,84 @@
+use gresources::settings::{Settings, StorageBackend};
+
+fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn table(toml: &str) -> toml::Table {
+    toml::from_str(toml).unwrap()
+}
+
+#[test]
+fn test_defaults_cover_every_setting() {
+    let settings = Settings::from_layers(toml::Table::new(), Vec::new()).unwrap();
+
+    assert_eq!(settings.db_file_path, "db/database.sqlite");
+    assert_eq!(settings.host, "127.0.0.1");
+    assert_eq!(settings.port, 8002);
+    assert_eq!(settings.storage_backend, StorageBackend::Sqlite);
+    assert_eq!(settings.max_resource_size, 5 * 1024 * 1024);
+    assert_eq!(settings.max_folder_depth, 5);
+    assert_eq!(settings.max_resource_name_length, 100);
+    assert_eq!(settings.workers, None);
+}
+
+#[test]
+fn test_environment_overrides_file() {
+    let settings = Settings::from_layers(
+        table("port = 9000\nhost = \"0.0.0.0\"\nmax_folder_depth = 8"),
+        env(&[
+            ("GRESOURCES_PORT", "9100"),
+            ("GRESOURCES_STORAGE_BACKEND", "memory"),
+            ("GRESOURCES_DB_FILE_PATH", "123"),
+            ("UNRELATED_PORT", "1"),
+        ]),
+    )
+    .unwrap();
+
+    assert_eq!(settings.port, 9100);
+    assert_eq!(settings.host, "0.0.0.0");
+    assert_eq!(settings.max_folder_depth, 8);
+    assert_eq!(settings.storage_backend, StorageBackend::Memory);
+    // Numeric-looking values still work for string settings
+    assert_eq!(settings.db_file_path, "123");
+    assert_eq!(settings.validation_limits().max_folder_depth, 8);
+}
+
+#[test]
+fn test_every_bad_key_is_reported() {
+    let error = Settings::from_layers(
+        table("port = \"eighty\"\ncolour = \"blue\"\nworkers = 0"),
+        env(&[("GRESOURCES_MAX_RESOURCE_SIZE", "huge"), ("GRESOURCES_PRT", "9000"), ("PRT", "9000")]),
+    )
+    .unwrap_err()
+    .to_string();
+
+    assert!(error.contains("port:"), "{}", error);
+    assert!(error.contains("colour: unknown setting"), "{}", error);
+    assert!(error.contains("GRESOURCES_PRT: unknown setting"), "{}", error);
+    assert!(!error.contains("\n  PRT"), "{}", error);
+    assert!(error.contains("max_resource_size:"), "{}", error);
+
+    let error = Settings::from_layers(
+        table("workers = 0\nmax_folder_depth = 0\nstorage_backend = \"filesystem\""),
+        Vec::new(),
+    )
+    .unwrap_err()
+    .to_string();
+
+    assert!(error.contains("workers: must be greater than 0"), "{}", error);
+    assert!(error.contains("max_folder_depth: must be greater than 0"), "{}", error);
+    assert!(error.contains("storage_root: required"), "{}", error);
+}
+
+#[test]
+fn test_explicit_config_path_must_exist() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(Settings::load_from(dir.path().join("missing.toml")).is_err());
+
+    let config = dir.path().join("custom.toml");
+    std::fs::write(&config, "port = 7000\n").unwrap();
+    assert_eq!(Settings::load_from(&config).unwrap().port, 7000);
+}
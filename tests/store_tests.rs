@@ -0,0 +1,649 @@
+use gresources::database::Database;
+use gresources::fs_store::FsStore;
+use gresources::memory_store::MemoryStore;
+use gresources::models::{ConflictPolicy, LoadReport, Resource, Usage};
+use gresources::quota::Quotas;
+use gresources::settings::Settings;
+use gresources::store::{ResourceStore, StoreError};
+use gresources::validation::{FolderPolicy, ValidationLimits};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+fn exercise_store(store: &dyn ResourceStore) {
+    let resource = Resource::new("/docs/readme".to_string(), "hello".to_string());
+    store.create_resource(&resource, &Quotas::UNLIMITED).unwrap();
+    store
+        .create_resource(&Resource::new("/docs/sub/nested".to_string(), "x".to_string()), &Quotas::UNLIMITED)
+        .unwrap();
+
+    assert!(store.resource_exists("/docs/readme").unwrap());
+    assert!(!store.resource_exists("/docs/missing").unwrap());
+    assert!(matches!(store.create_resource(&resource, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))));
+
+    let fetched = store.get_resource("/docs/readme").unwrap().unwrap();
+    assert_eq!(fetched.content.as_deref(), Some("hello"));
+    assert!(fetched.id.is_some());
+
+    store.update_resource("/docs/readme", "updated", &Quotas::UNLIMITED).unwrap();
+    let fetched = store.get_resource("/docs/readme").unwrap().unwrap();
+    assert_eq!(fetched.content.as_deref(), Some("updated"));
+    assert_eq!(fetched.size, 7);
+    assert!(matches!(store.update_resource("/docs/missing", "x", &Quotas::UNLIMITED), Err(StoreError::NotFound(_))));
+
+    let folder = store.list_folder_resources("/docs").unwrap();
+    assert_eq!(folder.resources, vec!["/docs/readme", "/docs/sub/nested"]);
+    assert_eq!(store.list_folder_resources("/").unwrap().resources.len(), 2);
+    assert!(matches!(store.list_folder_resources("/nothing"), Err(StoreError::NotFound(_))));
+
+    assert!(!store.folder_is_empty("/docs").unwrap());
+    assert!(store.folder_is_empty("/nothing").unwrap());
+
+    // A path is a resource or a folder, never both
+    for path in ["/docs/readme/child", "/docs", "/docs/sub", "/"] {
+        let clash = Resource::new(path.to_string(), "x".to_string());
+        assert!(matches!(store.create_resource(&clash, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))), "{}", path);
+    }
+    assert!(!store.resource_exists("/docs").unwrap());
+
+    store.delete_resource("/docs/sub/nested").unwrap();
+    assert!(store.folder_is_empty("/docs/sub").unwrap());
+    assert!(matches!(store.delete_resource("/docs/sub/nested"), Err(StoreError::NotFound(_))));
+    assert!(store.get_resource("/docs/sub/nested").unwrap().is_none());
+
+    let usage = store.usage().unwrap();
+    let expected = Usage { bytes: 7, resources: 1 };
+    assert_eq!(usage.user(1), expected);
+    assert_eq!(usage.folder("/docs"), expected);
+    assert_eq!(usage.folders.len(), 1);
+}
+
+#[test]
+fn test_memory_store() {
+    exercise_store(&MemoryStore::new());
+}
+
+#[test]
+fn test_sqlite_store() {
+    let dir = tempfile::tempdir().unwrap();
+    exercise_store(&common::database(&dir));
+}
+
+#[test]
+fn test_filesystem_store() {
+    let dir = tempfile::tempdir().unwrap();
+    exercise_store(&FsStore::new(dir.path(), ValidationLimits::default()).unwrap());
+
+    // Resources are plain files, so fixtures can be edited in place
+    let store = FsStore::new(dir.path(), ValidationLimits::default()).unwrap();
+    std::fs::write(dir.path().join("docs").join("manual"), "by hand").unwrap();
+    let fetched = store.get_resource("/docs/manual").unwrap().unwrap();
+    assert_eq!(fetched.content.as_deref(), Some("by hand"));
+    assert_eq!(
+        store.list_folder_resources("/docs").unwrap().resources,
+        vec!["/docs/manual", "/docs/readme"]
+    );
+
+    // The metadata directory is neither listable nor writable as a resource
+    assert!(matches!(store.get_resource("/.gresources/next_id"), Err(StoreError::Invalid(_))));
+    let reserved = Resource::new("/.gresources/x".to_string(), "x".to_string());
+    assert!(matches!(store.create_resource(&reserved, &Quotas::UNLIMITED), Err(StoreError::Invalid(_))));
+    let invalid = Resource::new("/a/b/c/d/e/f".to_string(), "x".to_string());
+    assert!(matches!(store.create_resource(&invalid, &Quotas::UNLIMITED), Err(StoreError::Invalid(_))));
+
+    // Writes replace files in one step and leave nothing behind
+    store.update_resource("/docs/manual", "edited", &Quotas::UNLIMITED).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.path().join("docs").join("manual")).unwrap(), "edited");
+    assert_eq!(std::fs::read_dir(dir.path().join(".gresources").join("tmp")).unwrap().count(), 0);
+
+    // Symbolic links are neither followed nor listed
+    #[cfg(unix)]
+    {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), "secret").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), dir.path().join("docs").join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("elsewhere")).unwrap();
+
+        assert!(matches!(store.get_resource("/docs/link"), Err(StoreError::Corrupt(_))));
+        assert!(matches!(store.get_resource("/elsewhere/secret"), Err(StoreError::Corrupt(_))));
+        let through_link = Resource::new("/elsewhere/planted".to_string(), "x".to_string());
+        assert!(store.create_resource(&through_link, &Quotas::UNLIMITED).is_err());
+        assert!(!outside.path().join("planted").exists());
+        assert!(store.delete_resource("/docs/link").is_err());
+        assert_eq!(std::fs::read_to_string(outside.path().join("secret")).unwrap(), "secret");
+        assert_eq!(
+            store.list_folder_resources("/docs").unwrap().resources,
+            vec!["/docs/manual", "/docs/readme"]
+        );
+    }
+}
+
+/// Several threads create, update and delete the same path. Every refusal
+/// must be a `Conflict` or `NotFound`, and each resource is deleted at most once.
+fn hammer_one_path(store: &dyn ResourceStore) {
+    const PATH: &str = "/race/target";
+    let created = AtomicUsize::new(0);
+    let deleted = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for thread in 0..8 {
+            let (created, deleted) = (&created, &deleted);
+            scope.spawn(move || {
+                for round in 0..50 {
+                    let content = format!("{}-{}", thread, round);
+                    match store.create_resource(&Resource::new(PATH.to_string(), content.clone()), &Quotas::UNLIMITED) {
+                        Ok(_) => _ = created.fetch_add(1, Ordering::SeqCst),
+                        Err(StoreError::Conflict(_)) => {}
+                        Err(e) => panic!("create failed: {}", e),
+                    }
+                    match store.update_resource(PATH, &content, &Quotas::UNLIMITED) {
+                        Ok(_) | Err(StoreError::NotFound(_)) => {}
+                        Err(e) => panic!("update failed: {}", e),
+                    }
+                    match store.delete_resource(PATH) {
+                        Ok(_) => _ = deleted.fetch_add(1, Ordering::SeqCst),
+                        Err(StoreError::NotFound(_)) => {}
+                        Err(e) => panic!("delete failed: {}", e),
+                    }
+                }
+            });
+        }
+    });
+
+    let remaining = store.get_resource(PATH).unwrap();
+    let (created, deleted) = (created.into_inner(), deleted.into_inner());
+    assert!(created > 0);
+    assert_eq!(created - deleted, usize::from(remaining.is_some()));
+
+    let expected = remaining.map_or(Usage::default(), |r| Usage { bytes: r.size, resources: 1 });
+    assert_eq!(store.usage().unwrap().folder("/race"), expected);
+}
+
+#[test]
+fn test_concurrent_writes_to_one_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = common::database(&dir);
+    hammer_one_path(&sqlite);
+    assert!(sqlite.check_integrity().unwrap().is_empty());
+
+    hammer_one_path(&MemoryStore::new());
+
+    let fs_dir = tempfile::tempdir().unwrap();
+    hammer_one_path(&FsStore::new(fs_dir.path(), ValidationLimits::default()).unwrap());
+}
+
+/// Threads race for the last resource slots of a folder. Quotas are checked
+/// with the write itself, so exactly the allowed number get through.
+fn exercise_quotas(store: &dyn ResourceStore) {
+    let quotas = Quotas {
+        folder_max_bytes: Some(100),
+        folder_max_resources: Some(10),
+        ..Quotas::UNLIMITED
+    };
+    let created = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let created = &created;
+            scope.spawn(move || {
+                for round in 0..5 {
+                    let resource = Resource::new(format!("/quota/{}-{}", thread, round), "x".to_string());
+                    match store.create_resource(&resource, &quotas) {
+                        Ok(_) => _ = created.fetch_add(1, Ordering::SeqCst),
+                        Err(StoreError::QuotaExceeded(_)) => {}
+                        Err(e) => panic!("create failed: {}", e),
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(created.into_inner(), 10);
+    assert_eq!(store.usage().unwrap().folder("/quota"), Usage { bytes: 10, resources: 10 });
+
+    // Updates, copies and loads that grow the folder are refused too
+    let path = store.list_folder_resources("/quota").unwrap().resources[0].clone();
+    let refused = |result: Result<_, StoreError>| matches!(result, Err(StoreError::QuotaExceeded(_)));
+    assert!(refused(store.update_resource(&path, &"x".repeat(100), &quotas).map(|_| ())));
+    assert!(refused(store.copy_resource(&path, "/quota/copy", None, &quotas).map(|_| ())));
+    let batch = [Resource::new("/quota/loaded".to_string(), "x".to_string())];
+    assert!(refused(store.load_resources(&batch, ConflictPolicy::Fail, &quotas).map(|_| ())));
+    assert!(!store.resource_exists("/quota/loaded").unwrap());
+
+    // Shrinking is always allowed
+    store.update_resource(&path, "", &quotas).unwrap();
+}
+
+#[test]
+fn test_stores_enforce_quotas_atomically() {
+    let dir = tempfile::tempdir().unwrap();
+    exercise_quotas(&common::database(&dir));
+    exercise_quotas(&MemoryStore::new());
+
+    let fs_dir = tempfile::tempdir().unwrap();
+    exercise_quotas(&FsStore::new(fs_dir.path(), ValidationLimits::default()).unwrap());
+}
+
+#[test]
+fn test_migrations_track_schema_version() {
+    use gresources::migrations::{current_version, latest_version, run_migrations};
+    use rusqlite::Connection;
+
+    let mut conn = Connection::open_in_memory().unwrap();
+    let dry_run = run_migrations(&mut conn, None, true).unwrap();
+    assert_eq!(dry_run.len(), latest_version() as usize);
+    assert_eq!(current_version(&conn).unwrap(), 0);
+
+    run_migrations(&mut conn, None, false).unwrap();
+    assert_eq!(current_version(&conn).unwrap(), latest_version());
+    assert!(run_migrations(&mut conn, None, false).unwrap().is_empty());
+
+    // A database written by a newer binary is refused
+    conn.pragma_update(None, "user_version", latest_version() + 1)
+        .unwrap();
+    assert!(run_migrations(&mut conn, None, true).is_err());
+}
+
+#[test]
+fn test_migrations_adopt_legacy_database() {
+    use gresources::migrations::{current_version, latest_version, run_migrations};
+    use rusqlite::Connection;
+
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(include_str!("../db/migrations/0001_initial.sql")).unwrap();
+    conn.execute("INSERT INTO resources (path, content) VALUES ('/a', 'x')", [])
+        .unwrap();
+
+    let pending = run_migrations(&mut conn, None, false).unwrap();
+    assert!(pending.iter().all(|m| m.version > 1));
+    assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+    // Usage totals are backfilled from the existing rows
+    let folder: (String, i64) = conn
+        .query_row("SELECT key, resources FROM usage WHERE scope = 'folder'", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(folder, ("/".to_string(), 1));
+
+    // Existing bodies move to the content store
+    let stored: (String, String, i64) = conn
+        .query_row(
+            "SELECT r.content_hash, CAST(c.data AS TEXT), c.refcount
+             FROM resources r JOIN contents c ON c.hash = r.content_hash",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(stored, (gresources::audit::content_hash("x"), "x".to_string(), 1));
+}
+
+#[test]
+fn test_schema_drift_is_reported() {
+    use gresources::migrations::{run_migrations, schema_drift};
+    use rusqlite::Connection;
+
+    let mut conn = Connection::open_in_memory().unwrap();
+    run_migrations(&mut conn, None, false).unwrap();
+    assert!(schema_drift(&conn).unwrap().is_empty());
+
+    conn.execute_batch("DROP INDEX idx_path; ALTER TABLE resources ADD COLUMN extra TEXT;")
+        .unwrap();
+    let drift = schema_drift(&conn).unwrap();
+    assert!(drift.contains(&"missing index idx_path".to_string()));
+    assert!(drift.contains(&"table resources: unexpected column extra".to_string()));
+}
+
+#[test]
+fn test_stale_schema_override_is_ignored() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut settings = Settings {
+        db_schema_path: Some(dir.path().join("schema.sql").to_string_lossy().into_owned()),
+        ..common::settings(&dir)
+    };
+
+    // A missing override falls back to the embedded schema
+    let store = Database::new(&settings).unwrap();
+    store.create_resource(&Resource::new("/a".to_string(), "x".to_string()), &Quotas::UNLIMITED).unwrap();
+    drop(store);
+
+    // Once the initial migration has run the override is not read again
+    std::fs::write(settings.db_schema_path.as_ref().unwrap(), "not sql").unwrap();
+    let store = Database::new(&settings).unwrap();
+    assert!(store.resource_exists("/a").unwrap());
+    drop(store);
+
+    // On a fresh database a broken override is still an error
+    settings.db_file_path = dir.path().join("fresh.sqlite").to_string_lossy().into_owned();
+    assert!(Database::new(&settings).is_err());
+}
+
+#[test]
+fn test_sqlite_snapshot_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = common::database(&dir);
+    assert!(store.supports_snapshots());
+    assert!(!MemoryStore::new().supports_snapshots());
+
+    store
+        .create_resource(&Resource::new("/keep".to_string(), "before".to_string()), &Quotas::UNLIMITED)
+        .unwrap();
+    let snapshot = store.create_snapshot("before-run").unwrap();
+    assert_eq!(snapshot.name, "before-run");
+    assert!(matches!(store.create_snapshot("before-run"), Err(StoreError::Conflict(_))));
+    assert!(matches!(store.create_snapshot("../escape"), Err(StoreError::Invalid(_))));
+
+    store.update_resource("/keep", "after", &Quotas::UNLIMITED).unwrap();
+    store
+        .create_resource(&Resource::new("/scratch".to_string(), "tmp".to_string()), &Quotas::UNLIMITED)
+        .unwrap();
+
+    let names: Vec<String> = store.list_snapshots().unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["before-run"]);
+
+    store.restore_snapshot("before-run").unwrap();
+    let restored = store.get_resource("/keep").unwrap().unwrap();
+    assert_eq!(restored.content.as_deref(), Some("before"));
+    assert!(store.get_resource("/scratch").unwrap().is_none());
+    assert!(matches!(store.restore_snapshot("missing"), Err(StoreError::NotFound(_))));
+    assert!(matches!(MemoryStore::new().list_snapshots(), Err(StoreError::Unsupported(_))));
+}
+
+#[test]
+fn test_sqlite_folder_policies() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = common::database(&dir);
+    assert!(store.supports_folder_policies());
+    assert!(store.folder_policies().unwrap().is_empty());
+
+    let policy = FolderPolicy {
+        folder: "/docs".to_string(),
+        max_folder_depth: Some(3),
+        max_resource_size: Some(1024),
+        name_pattern: Some("[a-z]+".to_string()),
+        content_types: vec!["text/plain".to_string()],
+        read_only: false,
+        json_schema: Some(serde_json::json!({"type": "object", "required": ["name"]})),
+    };
+    store.set_folder_policy(&policy).unwrap();
+    store
+        .set_folder_policy(&FolderPolicy {
+            folder: "/archive".to_string(),
+            read_only: true,
+            ..FolderPolicy::default()
+        })
+        .unwrap();
+
+    // Setting a policy again replaces it
+    let frozen = FolderPolicy {
+        read_only: true,
+        ..policy.clone()
+    };
+    store.set_folder_policy(&frozen).unwrap();
+    let folders: Vec<String> = store.folder_policies().unwrap().into_iter().map(|p| p.folder).collect();
+    assert_eq!(folders, vec!["/archive", "/docs"]);
+
+    assert_eq!(store.remove_folder_policy("/docs").unwrap(), frozen);
+    assert!(matches!(store.remove_folder_policy("/docs"), Err(StoreError::NotFound(_))));
+    assert_eq!(store.folder_policies().unwrap().len(), 1);
+
+    let memory = MemoryStore::new();
+    assert!(!memory.supports_folder_policies());
+    assert!(memory.folder_policies().unwrap().is_empty());
+    assert!(matches!(memory.set_folder_policy(&policy), Err(StoreError::Unsupported(_))));
+}
+
+#[test]
+fn test_sqlite_content_is_deduplicated() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = common::database(&dir);
+    let stored = || -> Vec<(String, i64)> {
+        let conn = rusqlite::Connection::open(dir.path().join("test.sqlite")).unwrap();
+        let mut stmt = conn.prepare("SELECT CAST(data AS TEXT) AS content, refcount FROM contents ORDER BY content").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        rows.map(Result::unwrap).collect()
+    };
+
+    for path in ["/runs/1/payload", "/runs/2/payload", "/runs/3/payload"] {
+        store.create_resource(&Resource::new(path.to_string(), "same".to_string()), &Quotas::UNLIMITED).unwrap();
+    }
+    store.create_resource(&Resource::new("/other".to_string(), "different".to_string()), &Quotas::UNLIMITED).unwrap();
+    assert_eq!(stored(), vec![("different".to_string(), 1), ("same".to_string(), 3)]);
+
+    let resource = store.get_resource("/runs/2/payload").unwrap().unwrap();
+    assert_eq!(resource.content_hash, Some(gresources::audit::content_hash("same")));
+
+    // Copies share the stored content
+    store.copy_resource("/runs/1/payload", "/runs/4/payload", None, &Quotas::UNLIMITED).unwrap();
+    let copy = store.get_resource("/runs/4/payload").unwrap().unwrap();
+    assert_eq!(copy.content.as_deref(), Some("same"));
+    assert_eq!(copy.size, 4);
+    assert!(matches!(store.copy_resource("/missing", "/x", None, &Quotas::UNLIMITED), Err(StoreError::NotFound(_))));
+    assert!(matches!(store.copy_resource("/other", "/runs/1/payload", None, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))));
+    assert!(matches!(store.copy_resource("/other", "/runs", None, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))));
+    assert_eq!(stored(), vec![("different".to_string(), 1), ("same".to_string(), 4)]);
+
+    // Rewriting the same content keeps it, the last reference removes it
+    store.update_resource("/other", "different", &Quotas::UNLIMITED).unwrap();
+    store.update_resource("/runs/1/payload", "different", &Quotas::UNLIMITED).unwrap();
+    assert_eq!(stored(), vec![("different".to_string(), 2), ("same".to_string(), 3)]);
+    for path in ["/runs/2/payload", "/runs/3/payload", "/runs/4/payload"] {
+        store.delete_resource(path).unwrap();
+    }
+    let batch = vec![Resource::new("/other".to_string(), "fresh".to_string())];
+    store.load_resources(&batch, ConflictPolicy::Overwrite, &Quotas::UNLIMITED).unwrap();
+    assert_eq!(stored(), vec![("different".to_string(), 1), ("fresh".to_string(), 1)]);
+    assert!(store.check_integrity().unwrap().is_empty());
+
+    // Broken reference counts are reported
+    let conn = rusqlite::Connection::open(dir.path().join("test.sqlite")).unwrap();
+    conn.execute("UPDATE contents SET refcount = 5 WHERE data = CAST('fresh' AS BLOB)", []).unwrap();
+    let problems = store.check_integrity().unwrap();
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].ends_with("reference count 5 but 1 resources refer to it"));
+
+    // Backends without a content store copy the content itself
+    let memory = MemoryStore::new();
+    memory.create_resource(&Resource::new("/a".to_string(), "x".to_string()), &Quotas::UNLIMITED).unwrap();
+    memory.copy_resource("/a", "/b", None, &Quotas::UNLIMITED).unwrap();
+    assert_eq!(memory.get_resource("/b").unwrap().unwrap().content.as_deref(), Some("x"));
+}
+
+#[test]
+fn test_sqlite_compresses_large_content() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = common::database(&dir);
+    let large = "line of a text fixture\n".repeat(200);
+    store.create_resource(&Resource::new("/large".to_string(), large.clone()), &Quotas::UNLIMITED).unwrap();
+    store.create_resource(&Resource::new("/small".to_string(), "tiny".to_string()), &Quotas::UNLIMITED).unwrap();
+
+    let conn = rusqlite::Connection::open(dir.path().join("test.sqlite")).unwrap();
+    let stored = |hash: String| -> (String, usize) {
+        conn.query_row("SELECT encoding, length(data) FROM contents WHERE hash = ?1", [hash], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+    };
+    let (encoding, stored_bytes) = stored(gresources::audit::content_hash(&large));
+    assert_eq!(encoding, "gzip");
+    assert!(stored_bytes < large.len() / 5, "{} bytes stored", stored_bytes);
+    assert_eq!(stored(gresources::audit::content_hash("tiny")).0, "identity");
+
+    // Reads see the logical content and size
+    let resource = store.get_resource("/large").unwrap().unwrap();
+    assert_eq!(resource.content.as_deref(), Some(large.as_str()));
+    assert_eq!(resource.size, large.len() as i64);
+
+    // The compressed body can be served as is
+    let (resource, gzipped) = store.get_resource_gzipped("/large").unwrap().unwrap();
+    assert!(resource.content.is_none());
+    let mut decompressed = String::new();
+    GzDecoder::new(&gzipped.unwrap()[..]).read_to_string(&mut decompressed).unwrap();
+    assert_eq!(decompressed, large);
+    let (resource, gzipped) = store.get_resource_gzipped("/small").unwrap().unwrap();
+    assert_eq!((resource.content.as_deref(), gzipped), (Some("tiny"), None));
+
+    store.update_resource("/large", &large.to_uppercase(), &Quotas::UNLIMITED).unwrap();
+    assert!(store.check_integrity().unwrap().is_empty());
+
+    // Compression can be turned off
+    let plain_dir = tempfile::tempdir().unwrap();
+    let plain = Database::new(&Settings {
+        compression_min_bytes: 0,
+        ..common::settings(&plain_dir)
+    })
+    .unwrap();
+    plain.create_resource(&Resource::new("/large".to_string(), large.clone()), &Quotas::UNLIMITED).unwrap();
+    assert!(plain.get_resource_gzipped("/large").unwrap().unwrap().1.is_none());
+}
+
+#[test]
+fn test_load_resources_fail_policy_is_all_or_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = common::database(&dir);
+    let memory = MemoryStore::new();
+
+    for store in [&sqlite as &dyn ResourceStore, &memory] {
+        store
+            .create_resource(&Resource::new("/import/taken".to_string(), "old".to_string()), &Quotas::UNLIMITED)
+            .unwrap();
+
+        let batch = vec![
+            Resource::new("/import/new".to_string(), "new".to_string()),
+            Resource::new("/import/taken".to_string(), "clash".to_string()),
+        ];
+        assert!(matches!(store.load_resources(&batch, ConflictPolicy::Fail, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))));
+        assert!(!store.resource_exists("/import/new").unwrap());
+
+        store.load_resources(&batch[..1], ConflictPolicy::Fail, &Quotas::UNLIMITED).unwrap();
+        assert_eq!(store.folder_resources("/import").unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn test_load_resources_respects_the_namespace() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = common::database(&dir);
+    let memory = MemoryStore::new();
+
+    for store in [&sqlite as &dyn ResourceStore, &memory] {
+        let batch = vec![
+            Resource::new("/tree/leaf".to_string(), "1".to_string()),
+            Resource::new("/tree/leaf/below".to_string(), "2".to_string()),
+        ];
+        for policy in [ConflictPolicy::Fail, ConflictPolicy::Skip, ConflictPolicy::Overwrite] {
+            assert!(matches!(store.load_resources(&batch, policy, &Quotas::UNLIMITED), Err(StoreError::Conflict(_))));
+        }
+        assert!(!store.resource_exists("/tree/leaf").unwrap());
+    }
+
+    // Rows written before the rule existed are reported by the integrity check
+    let conn = rusqlite::Connection::open(dir.path().join("test.sqlite")).unwrap();
+    for path in ["/old", "/old/child"] {
+        conn.execute(
+            "INSERT INTO resources (user_id, path, size, created_at, updated_at)
+             VALUES (1, ?1, 0, '2024-01-01T00:00:00.000Z', '2024-01-01T00:00:00.000Z')",
+            [path],
+        )
+        .unwrap();
+    }
+    let problems = sqlite.check_integrity().unwrap();
+    assert!(problems.contains(&"/old: is both a resource and a folder".to_string()), "{:?}", problems);
+}
+
+#[test]
+fn test_load_resources_conflict_policies() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = common::database(&dir);
+    let memory = MemoryStore::new();
+    let fs_dir = tempfile::tempdir().unwrap();
+    let filesystem = FsStore::new(fs_dir.path(), ValidationLimits::default()).unwrap();
+
+    for store in [&sqlite as &dyn ResourceStore, &memory, &filesystem] {
+        store
+            .create_resource(&Resource::new("/seed/a".to_string(), "old".to_string()), &Quotas::UNLIMITED)
+            .unwrap();
+        let batch = vec![
+            Resource::new("/seed/a".to_string(), "new".to_string()),
+            Resource::new("/seed/b".to_string(), "b".to_string()),
+        ];
+
+        let report = store.load_resources(&batch, ConflictPolicy::Skip, &Quotas::UNLIMITED).unwrap();
+        assert_eq!(report, LoadReport { created: 1, overwritten: 0, skipped: 1 });
+        assert_eq!(store.get_resource("/seed/a").unwrap().unwrap().content.as_deref(), Some("old"));
+
+        let report = store.load_resources(&batch, ConflictPolicy::Overwrite, &Quotas::UNLIMITED).unwrap();
+        assert_eq!(report, LoadReport { created: 0, overwritten: 2, skipped: 0 });
+        assert_eq!(store.get_resource("/seed/a").unwrap().unwrap().content.as_deref(), Some("new"));
+    }
+}
+
+#[test]
+fn test_sqlite_usage_survives_load_and_restore() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = common::database(&dir);
+    store
+        .create_resource(&Resource::new("/a/one".to_string(), "1234".to_string()), &Quotas::UNLIMITED)
+        .unwrap();
+    store.create_snapshot("before").unwrap();
+
+    let replacement = Resource::new("/a/one".to_string(), "12".to_string());
+    let added = Resource::new("/b/two".to_string(), "123".to_string());
+    store
+        .load_resources(&[replacement, added], ConflictPolicy::Overwrite, &Quotas::UNLIMITED)
+        .unwrap();
+    let usage = store.usage().unwrap();
+    assert_eq!(usage.user(1), Usage { bytes: 5, resources: 2 });
+    assert_eq!(usage.folder("/a"), Usage { bytes: 2, resources: 1 });
+    assert!(store.check_integrity().unwrap().is_empty());
+
+    store.restore_snapshot("before").unwrap();
+    let usage = store.usage().unwrap();
+    assert_eq!(usage.user(1), Usage { bytes: 4, resources: 1 });
+    assert_eq!(usage.folder("/b"), Usage::default());
+}
+
+#[test]
+fn test_folder_stats_match_across_backends() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = common::database(&dir);
+    let memory = MemoryStore::new();
+
+    let contents = [
+        ("/docs/readme", "hello"),
+        ("/docs/a/b/deep", "0123456789"),
+        ("/docs/a/c", "abc"),
+        ("/docs_other/x", "not below /docs"),
+        ("/top", "t"),
+    ];
+    for (path, content) in contents {
+        let resource = Resource::new(path.to_string(), content.to_string());
+        sqlite.create_resource(&resource, &Quotas::UNLIMITED).unwrap();
+        memory.create_resource(&resource, &Quotas::UNLIMITED).unwrap();
+    }
+
+    let stats = sqlite.folder_stats("/docs").unwrap().unwrap();
+    assert_eq!(stats.resources, 3);
+    assert_eq!(stats.bytes, 18);
+    assert_eq!(stats.folders, 2);
+    assert_eq!(stats.largest[0].path, "/docs/a/b/deep");
+    assert!(stats.oldest_updated_at <= stats.newest_updated_at);
+
+    for folder in ["/", "/docs", "/docs/a"] {
+        let sqlite_stats = sqlite.folder_stats(folder).unwrap().unwrap();
+        let memory_stats = memory.folder_stats(folder).unwrap().unwrap();
+        // SQLite keeps millisecond timestamps
+        assert_eq!(
+            (sqlite_stats.resources, sqlite_stats.bytes, sqlite_stats.folders, sqlite_stats.largest),
+            (memory_stats.resources, memory_stats.bytes, memory_stats.folders, memory_stats.largest),
+            "{}",
+            folder
+        );
+    }
+
+    assert!(sqlite.folder_stats("/missing").unwrap().is_none());
+    assert!(memory.folder_stats("/missing").unwrap().is_none());
+    assert_eq!(sqlite.folder_stats("/").unwrap().unwrap().folders, 4);
+}
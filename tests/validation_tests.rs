@@ -1,4 +1,10 @@
-use gresources::validation::{normalize_path, validate_content, validate_path};
+use gresources::validation::{
+    governing_policy, normalize_path, normalize_path_with_limits, normalize_request_path, validate_content,
+    validate_path, validate_path_with_limits, FolderPolicy, ValidationError, ValidationLimits,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use proptest::prelude::*;
+use serde_json::json;
 
 #[test]
 fn test_path_validation() {
@@ -30,4 +36,251 @@ fn test_path_normalization() {
     assert_eq!(normalize_path("/path/"), "/path");
     assert_eq!(normalize_path("/"), "/");
     assert_eq!(normalize_path("/path/to/resource"), "/path/to/resource");
+    assert_eq!(normalize_path("//a///b/"), "/a/b");
+    assert_eq!(normalize_path("///"), "/");
+    assert_eq!(normalize_path("/caf\u{65}\u{301}"), "/caf\u{e9}");
+
+    let limits = ValidationLimits::default();
+    assert_eq!(normalize_request_path("/a%20b/%C3%A9", &limits), "/a b/\u{e9}");
+    // Decoded once only, so an escaped escape stays literal
+    assert_eq!(normalize_request_path("/100%2541", &limits), "/100%41");
+    assert_eq!(
+        validate_path(&normalize_request_path("/bad%FF", &limits)),
+        Err(ValidationError::InvalidCharacters)
+    );
+    // An escaped slash is part of a name, not a separator, and names cannot hold one
+    for escaped in ["/docs%2Freadme", "/docs%2freadme", "/%2F", "/docs/..%2F..%2Fetc"] {
+        assert_eq!(
+            validate_path(&normalize_request_path(escaped, &limits)),
+            Err(ValidationError::InvalidCharacters)
+        );
+    }
+
+    let folding = ValidationLimits {
+        case_insensitive_paths: true,
+        ..ValidationLimits::default()
+    };
+    assert_eq!(normalize_path_with_limits("/Docs/README", &folding), "/docs/readme");
+    assert_eq!(validate_path_with_limits("/Docs", &folding), Err(ValidationError::NotCanonical));
+}
+
+#[test]
+fn test_unsafe_paths_are_rejected() {
+    assert_eq!(validate_path("/a/./b"), Err(ValidationError::DotSegment));
+    assert_eq!(validate_path("/a/.."), Err(ValidationError::DotSegment));
+    assert_eq!(validate_path("/a//b"), Err(ValidationError::NotCanonical));
+    assert_eq!(validate_path("/a/"), Err(ValidationError::NotCanonical));
+    assert_eq!(validate_path("/cafe\u{301}"), Err(ValidationError::NotCanonical));
+    for bad in ["/a\u{0}b", "/tab\tname", "/line\nbreak", "/zero\u{200B}width", "/rtl\u{202E}txt", "/bom\u{FEFF}"] {
+        assert_eq!(validate_path(bad), Err(ValidationError::InvalidCharacters), "{:?}", bad);
+    }
+    assert!(validate_path("/a.b/.hidden/\u{e9}t\u{e9}").is_ok());
+}
+
+#[test]
+fn test_folder_policies() {
+    let policies = vec![
+        FolderPolicy {
+            folder: "/docs".to_string(),
+            max_resource_size: Some(10),
+            ..FolderPolicy::default()
+        },
+        FolderPolicy {
+            folder: "/docs/api".to_string(),
+            max_folder_depth: Some(3),
+            name_pattern: Some("[a-z]+\\.json".to_string()),
+            content_types: vec!["application/json".to_string()],
+            ..FolderPolicy::default()
+        },
+        FolderPolicy {
+            folder: "/frozen".to_string(),
+            read_only: true,
+            ..FolderPolicy::default()
+        },
+    ];
+
+    // Longest matching folder wins, sibling prefixes do not match
+    let folder = |path| governing_policy(&policies, path).map(|p| p.folder.as_str());
+    assert_eq!(folder("/docs/api/v1.json"), Some("/docs/api"));
+    assert_eq!(folder("/docs/api"), Some("/docs/api"));
+    assert_eq!(folder("/docs/apis"), Some("/docs"));
+    assert_eq!(folder("/docsx"), None);
+
+    let api = &policies[1];
+    assert!(api.check_write("/docs/api/spec.json", "{}", Some("application/json; charset=utf-8")).is_ok());
+    assert!(api.check_write("/docs/api/spec.json", "{}", None).is_ok());
+    assert_eq!(
+        api.check_write("/docs/api/Spec.json", "{}", None),
+        Err(ValidationError::NameNotAllowed("[a-z]+\\.json".to_string()))
+    );
+    assert_eq!(
+        api.check_write("/docs/api/spec.json", "{}", Some("text/plain")),
+        Err(ValidationError::ContentTypeNotAllowed("text/plain".to_string()))
+    );
+    assert_eq!(api.check_write("/docs/api/v1/a.json", "{}", None), Err(ValidationError::TooDeep(3)));
+    assert_eq!(
+        policies[0].check_write("/docs/readme", "more than ten", None),
+        Err(ValidationError::ContentTooLarge(10))
+    );
+
+    let frozen = &policies[2];
+    assert_eq!(frozen.check_delete(), Err(ValidationError::ReadOnly("/frozen".to_string())));
+    assert_eq!(frozen.check_write("/frozen/a", "", None), Err(ValidationError::ReadOnly("/frozen".to_string())));
+
+    let limits = ValidationLimits::default();
+    assert!(policies.iter().all(|p| p.validate(&limits).is_ok()));
+    let broken = FolderPolicy {
+        folder: "/x".to_string(),
+        name_pattern: Some("(".to_string()),
+        ..FolderPolicy::default()
+    };
+    assert!(matches!(broken.validate(&limits), Err(ValidationError::InvalidPolicy(_))));
+    let untyped = FolderPolicy {
+        folder: "/x".to_string(),
+        content_types: vec!["json".to_string()],
+        ..FolderPolicy::default()
+    };
+    assert!(matches!(untyped.validate(&limits), Err(ValidationError::InvalidPolicy(_))));
+}
+
+#[test]
+fn test_json_schema_policies() {
+    let policy = FolderPolicy {
+        folder: "/configs".to_string(),
+        json_schema: Some(json!({
+            "type": "object",
+            "properties": {"port": {"type": "integer"}, "host": {"type": "string"}},
+            "required": ["host"],
+        })),
+        ..FolderPolicy::default()
+    };
+    assert!(policy.validate(&ValidationLimits::default()).is_ok());
+    assert!(policy.check_write("/configs/a", r#"{"host": "x", "port": 80}"#, None).is_ok());
+
+    // Every violation is reported, with a pointer to where it is
+    let Err(ValidationError::SchemaViolations(violations)) = policy.check_write("/configs/a", r#"{"port": "80"}"#, None)
+    else {
+        panic!("expected schema violations");
+    };
+    let pointers: Vec<&str> = violations.iter().map(|v| v.pointer.as_str()).collect();
+    assert_eq!(violations.len(), 2);
+    assert!(pointers.contains(&"") && pointers.contains(&"/port"), "{:?}", violations);
+
+    let Err(ValidationError::SchemaViolations(violations)) = policy.check_write("/configs/a", "not json", None) else {
+        panic!("expected a parse error");
+    };
+    assert!(violations[0].message.starts_with("Invalid JSON"));
+
+    let broken = FolderPolicy {
+        json_schema: Some(json!({"type": "no-such-type"})),
+        ..policy
+    };
+    assert!(matches!(broken.validate(&ValidationLimits::default()), Err(ValidationError::InvalidPolicy(_))));
+}
+
+/// Path-like strings mixing separators, dots, escapes, case and characters
+/// with several Unicode representations.
+fn messy_path() -> impl Strategy<Value = String> {
+    let piece = prop_oneof![
+        Just("/".to_string()),
+        Just("//".to_string()),
+        Just(".".to_string()),
+        Just("%".to_string()),
+        Just("e\u{301}".to_string()),
+        Just("\u{e9}".to_string()),
+        Just("\u{212B}".to_string()), // ANGSTROM SIGN, NFC maps it to U+00C5
+        "[a-zA-Z0-9_-]{1,4}",
+        any::<char>().prop_map(String::from),
+    ];
+    prop::collection::vec(piece, 0..12).prop_map(|pieces| format!("/{}", pieces.concat()))
+}
+
+fn limits(case_insensitive_paths: bool) -> ValidationLimits {
+    ValidationLimits {
+        case_insensitive_paths,
+        max_folder_depth: 100,
+        max_resource_name_length: 1000,
+        ..ValidationLimits::default()
+    }
+}
+
+proptest! {
+    #[test]
+    fn prop_normalization_is_idempotent(path in messy_path(), fold in any::<bool>()) {
+        let limits = limits(fold);
+        let once = normalize_path_with_limits(&path, &limits);
+        prop_assert_eq!(normalize_path_with_limits(&once, &limits), once);
+    }
+
+    #[test]
+    fn prop_normalized_paths_are_canonical(path in messy_path(), fold in any::<bool>()) {
+        let limits = limits(fold);
+        let normalized = normalize_path_with_limits(&path, &limits);
+        prop_assert!(!normalized.contains("//"));
+        prop_assert!(normalized == "/" || !normalized.ends_with('/'));
+        // Whatever else is wrong with it, a normalized path is never rejected as non-canonical
+        prop_assert_ne!(validate_path_with_limits(&normalized, &limits), Err(ValidationError::NotCanonical));
+    }
+
+    #[test]
+    fn prop_only_canonical_paths_validate(path in messy_path(), fold in any::<bool>()) {
+        let limits = limits(fold);
+        if validate_path_with_limits(&path, &limits).is_ok() {
+            prop_assert_eq!(normalize_path_with_limits(&path, &limits), path);
+        }
+    }
+
+    #[test]
+    fn prop_percent_escapes_decode_exactly_once(segments in prop::collection::vec("[^/\\pC]{1,8}", 1..4)) {
+        let limits = limits(false);
+        let plain = format!("/{}", segments.join("/"));
+        let encoded: Vec<String> = segments
+            .iter()
+            .map(|s| utf8_percent_encode(s, NON_ALPHANUMERIC).to_string())
+            .collect();
+        let request = format!("/{}", encoded.join("/"));
+        prop_assert_eq!(normalize_request_path(&request, &limits), normalize_path(&plain));
+
+        // Encoding twice leaves one level of escapes in the stored path
+        let twice = utf8_percent_encode(&request, NON_ALPHANUMERIC).to_string().replace("%2F", "/");
+        prop_assert_eq!(normalize_request_path(&twice, &limits), normalize_path(&request));
+    }
+
+    #[test]
+    fn prop_escaped_slashes_never_separate(segments in prop::collection::vec("\\PC{0,8}", 1..4)) {
+        let encoded: Vec<String> = segments
+            .iter()
+            .map(|s| utf8_percent_encode(s, NON_ALPHANUMERIC).to_string())
+            .collect();
+        let request = format!("/{}", encoded.join("/"));
+        let limits = limits(false);
+        let normalized = normalize_request_path(&request, &limits);
+        prop_assert!(normalized.matches('/').count() <= segments.len());
+        if segments.iter().any(|s| s.contains('/')) {
+            prop_assert!(normalized.contains(char::REPLACEMENT_CHARACTER));
+            prop_assert!(validate_path_with_limits(&normalized, &limits).is_err());
+        }
+    }
+
+    #[test]
+    fn prop_unicode_forms_and_case_collapse(word in "[a-zA-Z]{1,8}", composed in any::<bool>()) {
+        let accent = if composed { "\u{e9}" } else { "e\u{301}" };
+        let upper = format!("/{}{}", word.to_uppercase(), accent);
+        let lower = format!("/{}{}", word.to_lowercase(), accent);
+
+        prop_assert_eq!(normalize_path(&upper), normalize_path(&format!("/{}\u{e9}", word.to_uppercase())));
+        let folding = limits(true);
+        prop_assert_eq!(normalize_path_with_limits(&upper, &folding), normalize_path_with_limits(&lower, &folding));
+        prop_assert_ne!(normalize_path_with_limits(&upper, &limits(false)), normalize_path_with_limits(&lower, &limits(false)));
+    }
+
+    #[test]
+    fn prop_control_characters_are_always_rejected(
+        prefix in "[a-z]{0,5}",
+        suffix in "[a-z]{0,5}",
+        control in prop_oneof![0u32..0x20, 0x7fu32..0xa0].prop_map(|c| char::from_u32(c).unwrap()),
+    ) {
+        let path = format!("/dir/{}{}{}", prefix, control, suffix);
+        prop_assert_eq!(validate_path(&path), Err(ValidationError::InvalidCharacters));
+    }
 }